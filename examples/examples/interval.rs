@@ -7,7 +7,6 @@ use trace4rs::{
     config::{
         self,
         Config,
-        Format,
     },
     Handle,
 };
@@ -16,18 +15,15 @@ use tracing::info;
 fn main() {
     // Create the handle
     let config = {
-        let file = config::Appender::File {
-            path: "./file.log".into(),
-        };
-        let default = config::Logger {
-            level:     config::LevelFilter::INFO,
-            appenders: literally::hset! {"file"},
-            format:    Format::default(),
-        };
+        let file = config::Appender::file("./file.log");
+        let default = config::Logger::new(config::LevelFilter::INFO, ["file"]);
         Config {
             default,
             loggers: Default::default(),
             appenders: literally::hmap! {"file" => file},
+            spans: literally::hmap! {},
+            context: literally::hmap! {},
+            redact: vec![],
         }
     };
     let (_, s) = <Handle>::from_config(&config).unwrap();
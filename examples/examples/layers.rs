@@ -8,7 +8,6 @@ use trace4rs::{
     config::{
         self,
         Config,
-        Format,
     },
     handle::{
         ExtendedT4,
@@ -35,18 +34,15 @@ use tracing_subscriber::{
 
 fn main() {
     let config = {
-        let file = config::Appender::File {
-            path: "file.log".into(),
-        };
-        let default = config::Logger {
-            level:     config::LevelFilter::INFO,
-            appenders: literally::hset! {"file"},
-            format:    Format::default(),
-        };
+        let file = config::Appender::file("file.log");
+        let default = config::Logger::new(config::LevelFilter::INFO, ["file"]);
         Config {
             default,
             loggers: Default::default(),
             appenders: literally::hmap! {"file" => file},
+            spans: literally::hmap! {},
+            context: literally::hmap! {},
+            redact: vec![],
         }
     };
 
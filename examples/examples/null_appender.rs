@@ -20,24 +20,15 @@ async fn main() {
 
     // Create the handle
     let config = {
-        let default = config::Logger {
-            level:     config::LevelFilter::TRACE,
-            appenders: literally::hset! {"file"},
-            format:    config::Format::default(),
-        };
+        let default = config::Logger::new(config::LevelFilter::TRACE, ["file"]);
         let loggers = {
-            let hush = config::Logger {
-                level:     config::LevelFilter::TRACE,
-                appenders: literally::hset! {"hush"},
-                format:    config::Format::MessageOnly,
-            };
+            let mut hush = config::Logger::new(config::LevelFilter::TRACE, ["hush"]);
+            hush.format = config::Format::MessageOnly;
             literally::hmap! {"hush" => hush}
         };
         let appenders = {
             let hush = config::Appender::Null;
-            let file = config::Appender::File {
-                path: log_path.to_string_lossy().into_owned(),
-            };
+            let file = config::Appender::file(log_path.to_string_lossy());
             literally::hmap! {
                 "file" => file,
                 "hush" => hush,
@@ -47,6 +38,9 @@ async fn main() {
             default,
             loggers,
             appenders,
+            spans: literally::hmap! {},
+            context: literally::hmap! {},
+            redact: vec![],
         }
     };
     let (_h, s) = <Handle>::from_config(&config).unwrap();
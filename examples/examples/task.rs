@@ -11,7 +11,6 @@ use trace4rs::{
     config::{
         self,
         Config,
-        Format,
     },
     Handle,
 };
@@ -25,27 +24,20 @@ async fn main() {
     // Create the handle
     let config = {
         let console = config::Appender::Console;
-        let file = config::Appender::File {
-            path: file_out_lossy.clone().into_owned(),
-        };
+        let file = config::Appender::file(file_out_lossy.clone());
         let appenders = literally::hmap! {
             "console" => console,
             "file" => file,
         };
-        let default = config::Logger {
-            level:     config::LevelFilter::WARN,
-            appenders: literally::hset! {"console"},
-            format:    Format::default(),
-        };
-        let l1 = config::Logger {
-            level:     config::LevelFilter::INFO,
-            appenders: literally::hset! {"file"},
-            format:    Format::default(),
-        };
+        let default = config::Logger::new(config::LevelFilter::WARN, ["console"]);
+        let l1 = config::Logger::new(config::LevelFilter::INFO, ["file"]);
         Config {
             default,
             loggers: literally::hmap! {"trace4rs" => l1},
             appenders,
+            spans: literally::hmap! {},
+            context: literally::hmap! {},
+            redact: vec![],
         }
     };
     let (h, s) = <Handle>::from_config(&config).unwrap();
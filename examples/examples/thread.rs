@@ -9,7 +9,6 @@ use trace4rs::{
     config::{
         self,
         Config,
-        Format,
     },
     Handle,
 };
@@ -27,20 +26,15 @@ fn main() {
             "console" => console,
             "file" => file,
         };
-        let default = config::Logger {
-            level:     config::LevelFilter::WARN,
-            appenders: literally::hset! {"console"},
-            format:    Format::default(),
-        };
-        let l1 = config::Logger {
-            level:     config::LevelFilter::INFO,
-            appenders: literally::hset! {"file"},
-            format:    Format::default(),
-        };
+        let default = config::Logger::new(config::LevelFilter::WARN, ["console"]);
+        let l1 = config::Logger::new(config::LevelFilter::INFO, ["file"]);
         Config {
             default,
             loggers: literally::hmap! {"trace4rs" => l1},
             appenders,
+            spans: literally::hmap! {},
+            context: literally::hmap! {},
+            redact: vec![],
         }
     };
     let (h, s) = <Handle>::from_config(&config).unwrap();
@@ -6,6 +6,7 @@ use std::{
         HashMap,
         HashSet,
     },
+    fmt,
     result,
     str::FromStr,
 };
@@ -21,10 +22,8 @@ use serde::{
 };
 use tracing::level_filters;
 
-use crate::error::{
-    Error,
-    Result,
-};
+// not importing `error::Result`, schemars' derive refers to `Result` unqualified
+use crate::error::Error;
 
 /// The root configuration object containing everything necessary to build a
 /// `trace4rs::Handle`.
@@ -49,6 +48,35 @@ pub struct Config {
         serde(serialize_with = "ordered_map")
     )]
     pub loggers:   HashMap<Target, Logger>,
+    /// Spans which get a log file of their own, by span name. Events within
+    /// such a span are written to its file in addition to the loggers.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    #[cfg_attr(
+        feature = "in-order-serialization",
+        serde(serialize_with = "ordered_map")
+    )]
+    pub spans:     HashMap<String, SpanLog>,
+    /// Fields written with every event, e.g. the service name and version.
+    /// Values may refer to environment variables as `$ENV{VAR}`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    #[cfg_attr(
+        feature = "in-order-serialization",
+        serde(serialize_with = "ordered_map")
+    )]
+    pub context:   HashMap<String, String>,
+    /// Rules keeping sensitive values, e.g. passwords or card numbers, out of
+    /// the output.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub redact:    Vec<Redaction>,
 }
 
 /// # Errors
@@ -91,24 +119,53 @@ impl Default for Config {
 impl Config {
     /// A configuration for `INFO` and above to be logged to stdout.
     fn console_config() -> Config {
-        use literally::{
-            hmap,
-            hset,
-        };
+        use literally::hmap;
 
         Config {
-            default:   Logger {
-                level:     LevelFilter::INFO,
-                appenders: hset! { "stdout" },
-                format:    Format::default(),
-            },
+            default:   Logger::new(LevelFilter::INFO, ["stdout"]),
             loggers:   hmap! {},
             appenders: hmap! {
                 "stdout" => Appender::Console
             },
+            spans:     hmap! {},
+            context:   hmap! {},
+            redact:    vec![],
+        }
+    }
+}
+/// A `SpanLog` writes the events within a span to a file which is opened when
+/// the span is created and closed when it closes.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct SpanLog {
+    /// The path of the file, `$FIELD{<name>}` is replaced by the value of the
+    /// span's field `<name>`, for example `jobs/$FIELD{id}.log`.
+    pub path:   String,
+    #[cfg_attr(feature = "serde", serde(default = "SpanLog::default_level"))]
+    pub level:  LevelFilter,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "Format::default", skip_serializing_if = "Format::is_normal")
+    )]
+    pub format: Format,
+}
+impl SpanLog {
+    #[must_use]
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path:   path.into(),
+            level:  Self::default_level(),
+            format: Format::default(),
         }
     }
+
+    #[must_use]
+    pub fn default_level() -> LevelFilter {
+        LevelFilter::TRACE
+    }
 }
+
 /// A log target, for example to capture all log messages in `trace4rs::config`
 /// the target would be `trace4rs::config`.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -140,21 +197,138 @@ impl ToString for Target {
 pub struct AppenderId(pub String);
 
 /// A logger allows for filtering events and delegating to multiple appenders.
+/// Build one with `Logger::new` and set the options it needs.
 #[derive(PartialEq, Eq, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[non_exhaustive]
 pub struct Logger {
     #[cfg_attr(
         feature = "in-order-serialization",
         serde(serialize_with = "ordered_set")
     )]
-    pub appenders: HashSet<AppenderId>,
-    pub level:     LevelFilter,
+    pub appenders:  HashSet<AppenderId>,
+    pub level:      LevelFilter,
     #[cfg_attr(
         feature = "serde",
         serde(default = "Format::default", skip_serializing_if = "Format::is_normal")
     )]
-    pub format:    Format,
+    pub format:     Format,
+    /// Limits how many events the logger passes on to its appenders.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "rateLimit",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
+    pub rate_limit: Option<RateLimit>,
+    /// Keeps only a share of the logger's less severe events.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub sample:     Option<Sample>,
+    /// The time zone of the logger's timestamps.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Timezone::is_local")
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+    pub timezone:   Timezone,
+    /// The format of the logger's timestamps: a `time` format description such
+    /// as `[hour]:[minute]:[second].[subsecond digits:3]`, `rfc3339` (the
+    /// default), `epoch` or `epoch_ms`. Applies to the normal format and to
+    /// `{T}` fields of custom formats without a format of their own.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub timestamp:  Option<String>,
+    /// Keeps messages and field values from forging lines or writing terminal
+    /// escapes, by default they are written as they are.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub sanitize:   Option<Sanitize>,
+}
+
+impl Logger {
+    /// A logger of events at `level` and above to `appenders`, in the normal
+    /// format and with the other options at their defaults.
+    #[must_use]
+    pub fn new<A: Into<AppenderId>>(
+        level: LevelFilter,
+        appenders: impl IntoIterator<Item = A>,
+    ) -> Self {
+        Self {
+            appenders: appenders.into_iter().map(Into::into).collect(),
+            level,
+            format: Format::default(),
+            rate_limit: None,
+            sample: None,
+            timezone: Timezone::default(),
+            timestamp: None,
+            sanitize: None,
+        }
+    }
+}
+
+/// How a logger makes the messages and field values it writes safe, so that
+/// e.g. a user name containing a newline can't pass for an event of its own.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Sanitize {
+    /// Control characters are escaped as in `\n`, `\r` or `\u{1b}`, which
+    /// also disarms ANSI escape sequences.
+    Escape,
+    /// As `Escape`, except that line breaks are kept and the lines following
+    /// them indented, for multi-line messages such as backtraces.
+    Indent,
+}
+
+/// A `RateLimit` is a token bucket: up to `burst` events pass at once, after
+/// which events pass at `rate` per second. Suppressed events are summarized
+/// by a warning once events from the same source pass again, or within a
+/// second by a background sweep if they don't.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct RateLimit {
+    /// Events per second.
+    pub rate:  u32,
+    /// The size of the bucket, i.e. the largest burst of events let through.
+    pub burst: u32,
+    /// What buckets are kept for.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub per:   RateLimitKey,
+}
+
+/// What a `RateLimit` keeps a separate bucket for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum RateLimitKey {
+    /// Each `tracing` callsite, or source location of a `log` record.
+    #[default]
+    Callsite,
+    /// Each event target.
+    Target,
 }
 
 #[cfg(feature = "serde")]
@@ -230,6 +404,46 @@ mod format {
             deserializer.deserialize_str(V)
         }
     }
+
+    /// `{ "log4rs": "<pattern>" }`
+    pub mod log4rs {
+        use serde::{
+            Deserialize,
+            Serialize,
+        };
+
+        #[derive(Serialize, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Log4rs<'a> {
+            #[serde(borrow)]
+            log4rs: std::borrow::Cow<'a, str>,
+        }
+
+        pub fn serialize<S>(value: &str, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            Log4rs {
+                log4rs: value.into(),
+            }
+            .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<String, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            Log4rs::deserialize(deserializer).map(|l| l.log4rs.into_owned())
+        }
+    }
+}
+
+/// The serialized form of `Format::Log4rsPattern`.
+#[cfg(feature = "schemars")]
+#[derive(JsonSchema)]
+#[allow(dead_code)] // only describes the schema
+struct Log4rsPattern {
+    log4rs: String,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Default)]
@@ -250,6 +464,11 @@ pub enum Format {
     #[cfg_attr(feature = "serde", serde(with = "format::custom"))]
     #[cfg_attr(feature = "schemars", schemars(with = "String"))]
     Custom(String),
+    /// A log4rs pattern, e.g. `{d(%Y-%m-%d %H:%M:%S)} {h({l})} {M}:{L} -
+    /// {m}{n}`, written as a custom format.
+    #[cfg_attr(feature = "serde", serde(with = "format::log4rs"))]
+    #[cfg_attr(feature = "schemars", schemars(with = "Log4rsPattern"))]
+    Log4rsPattern(String),
 }
 impl Format {
     #[cfg(feature = "serde")]
@@ -259,6 +478,83 @@ impl Format {
     }
 }
 
+/// The time zone timestamps are written in: `local`, `utc` or a fixed offset
+/// such as `+02:00`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Timezone {
+    /// The local offset, looked up periodically so that daylight saving
+    /// changes are picked up.
+    #[default]
+    Local,
+    Utc,
+    /// A fixed offset from UTC in seconds, positive east of Greenwich.
+    Fixed(i32),
+}
+impl Timezone {
+    #[must_use]
+    pub fn is_local(&self) -> bool {
+        matches!(self, Self::Local)
+    }
+}
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => f.write_str("local"),
+            Self::Utc => f.write_str("utc"),
+            Self::Fixed(secs) => {
+                let sign = if *secs < 0 { '-' } else { '+' };
+                let mins = secs.unsigned_abs() / 60;
+                write!(f, "{sign}{:02}:{:02}", mins / 60, mins % 60)
+            },
+        }
+    }
+}
+impl FromStr for Timezone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let invalid = || Error::InvalidTimezone(s.to_string());
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(Self::Utc);
+        }
+        let (sign, rest) = match s.get(..1) {
+            Some("+") => (1_i32, s.get(1..).unwrap_or_default()),
+            Some("-") => (-1, s.get(1..).unwrap_or_default()),
+            _ => return Err(invalid()),
+        };
+        let (hours, mins) = rest.split_once(':').unwrap_or((rest, "0"));
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let mins: i32 = mins.parse().map_err(|_| invalid())?;
+        if !(0..=23).contains(&hours) || !(0..=59).contains(&mins) {
+            return Err(invalid());
+        }
+        let secs = hours.saturating_mul(3600).saturating_add(mins.saturating_mul(60));
+        Ok(Self::Fixed(sign.saturating_mul(secs)))
+    }
+}
+#[cfg(feature = "serde")]
+impl Serialize for Timezone {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        FromStr::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
 /// Simply a wrapper around `tracing::LevelFilter` such that it can be used by
 /// `serde`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -314,7 +610,8 @@ impl FromStr for LevelFilter {
     }
 }
 
-/// An Appender specifies a single event sink.
+/// An Appender specifies a single event sink. The file appenders are built
+/// with `Appender::file`, `Appender::rolling_file` and `Appender::sifting`.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
 #[cfg_attr(
@@ -325,25 +622,184 @@ impl FromStr for LevelFilter {
 pub enum Appender {
     Null,
     Console,
+    #[non_exhaustive]
     File {
-        path: String,
+        path:      String,
+        #[cfg_attr(
+            feature = "serde",
+            serde(
+                rename = "onError",
+                default,
+                skip_serializing_if = "ErrorPolicy::is_ignore"
+            )
+        )]
+        on_error:  ErrorPolicy,
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        dedupe:    Option<Dedupe>,
+        /// When output is fsynced to disk.
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "SyncPolicy::is_never")
+        )]
+        sync:      SyncPolicy,
+        /// Write and sync failures are reported by the next flush of the
+        /// appender.
+        #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_false"))]
+        audit:     bool,
+        /// Append a running hash to every line to make edits detectable.
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        integrity: Option<Integrity>,
+        /// Other processes append to and roll the same file, writes and rolls
+        /// are done holding an advisory lock on `<path>.lock`.
+        #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_false"))]
+        shared:    bool,
     },
+    #[non_exhaustive]
     RollingFile {
-        path:   String,
+        path:      String,
         #[cfg_attr(feature = "serde", serde(rename = "rolloverPolicy"))]
-        policy: Policy,
+        policy:    Policy,
+        #[cfg_attr(
+            feature = "serde",
+            serde(
+                rename = "onError",
+                default,
+                skip_serializing_if = "ErrorPolicy::is_ignore"
+            )
+        )]
+        on_error:  ErrorPolicy,
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        dedupe:    Option<Dedupe>,
+        /// When output is fsynced to disk.
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "SyncPolicy::is_never")
+        )]
+        sync:      SyncPolicy,
+        /// Write and sync failures are reported by the next flush of the
+        /// appender.
+        #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_false"))]
+        audit:     bool,
+        /// Append a running hash to every line to make edits detectable.
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        integrity: Option<Integrity>,
+        /// Other processes append to and roll the same file, writes and rolls
+        /// are done holding an advisory lock on `<path>.lock`.
+        #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_false"))]
+        shared:    bool,
+    },
+    /// A file per value of an event or span field, created when the value is
+    /// first seen.
+    #[non_exhaustive]
+    Sifting {
+        /// The name of the event field, or field of an enclosing span, whose
+        /// value picks the file.
+        field: String,
+        /// The path of each file, `$FIELD{<field>}` is replaced with the
+        /// value, percent-encoded where it isn't safe in a file name.
+        path: String,
+        /// Roll the files with this policy, they are never rolled otherwise.
+        #[cfg_attr(
+            feature = "serde",
+            serde(
+                rename = "rolloverPolicy",
+                default,
+                skip_serializing_if = "Option::is_none"
+            )
+        )]
+        policy: Option<Policy>,
+        /// The value used for events without the field.
+        #[cfg_attr(feature = "serde", serde(default = "Appender::default_fallback"))]
+        fallback: String,
+        /// Files which haven't been written to for this long are closed.
+        #[cfg_attr(
+            feature = "serde",
+            serde(
+                rename = "idleTimeoutMs",
+                default = "Appender::default_idle_timeout_ms"
+            )
+        )]
+        idle_timeout_ms: u64,
+        /// The most files kept open at once, the least recently written is
+        /// closed to make room.
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "maxOpen", default = "Appender::default_max_open")
+        )]
+        max_open: u32,
     },
 }
 
 impl Appender {
     pub fn file(path: impl Into<String>) -> Self {
-        Self::File { path: path.into() }
+        Self::File {
+            path:      path.into(),
+            on_error:  ErrorPolicy::default(),
+            dedupe:    None,
+            sync:      SyncPolicy::default(),
+            audit:     false,
+            integrity: None,
+            shared:    false,
+        }
+    }
+
+    pub fn rolling_file(path: impl Into<String>, policy: Policy) -> Self {
+        Self::RollingFile {
+            path: path.into(),
+            policy,
+            on_error: ErrorPolicy::default(),
+            dedupe: None,
+            sync: SyncPolicy::default(),
+            audit: false,
+            integrity: None,
+            shared: false,
+        }
+    }
+
+    /// A sifting appender with the default fallback value, idle timeout and
+    /// cap on open files.
+    pub fn sifting(field: impl Into<String>, path: impl Into<String>) -> Self {
+        Self::Sifting {
+            field: field.into(),
+            path: path.into(),
+            policy: None,
+            fallback: Self::default_fallback(),
+            idle_timeout_ms: Self::default_idle_timeout_ms(),
+            max_open: Self::default_max_open(),
+        }
     }
 
     #[must_use]
     pub fn console() -> Self {
         Self::Console
     }
+
+    #[must_use]
+    pub fn default_fallback() -> String {
+        "unknown".to_string()
+    }
+
+    #[must_use]
+    pub fn default_idle_timeout_ms() -> u64 {
+        5 * 60 * 1000
+    }
+
+    #[must_use]
+    pub fn default_max_open() -> u32 {
+        64
+    }
 }
 impl From<&str> for AppenderId {
     fn from(s: &str) -> Self {
@@ -351,6 +807,255 @@ impl From<&str> for AppenderId {
     }
 }
 
+/// A `Redaction` replaces the values of the fields whose name matches `field`
+/// and the parts of values, the message included, which match `pattern`
+/// before events are written. With both, only the parts matching `pattern` of
+/// the fields matching `field` are replaced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Redaction {
+    /// A glob on field names, e.g. `*password*`, where `*` matches any
+    /// characters and `?` a single one. Case is ignored.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub field:   Option<String>,
+    /// A regular expression, e.g. `\\b(?:\\d[ -]?){13,16}\\b` for card numbers.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub pattern: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Replacement::is_mask")
+    )]
+    pub replace: Replacement,
+    /// The key of the HMAC values are hashed with, required by
+    /// `Replacement::Hash`. Env variables are expanded with the following
+    /// syntax: `$ENV{var_name}`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub key:     Option<String>,
+}
+
+/// What a `Redaction` replaces values with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Replacement {
+    /// Nothing, the field or the matching part of the value is left out.
+    Remove,
+    /// `***`.
+    #[default]
+    Mask,
+    /// The first 16 hex digits of the HMAC-SHA-256 of the value under the
+    /// rule's `key`, so that equal values can still be told apart from others.
+    /// Without the key, values can't be recovered by hashing guesses.
+    Hash,
+}
+impl Replacement {
+    #[cfg(feature = "serde")]
+    #[allow(clippy::trivially_copy_pass_by_ref)] // required by serde
+    fn is_mask(&self) -> bool {
+        matches!(self, Self::Mask)
+    }
+}
+
+/// An `ErrorPolicy` specifies what an appender does when writing to its sink
+/// fails, for example when the disk is full.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
+pub enum ErrorPolicy {
+    /// The output is dropped, the error is only counted.
+    #[default]
+    Ignore,
+    /// The output is dropped, the first error is reported to stderr and
+    /// subsequent errors are only counted.
+    ReportOnce,
+    /// The write is retried up to `attempts` times, doubling the wait between
+    /// attempts starting from `backoff_ms`. Nothing waits: an attempt is made
+    /// by the next write or flush after the wait, and output written
+    /// meanwhile is held back behind the failed write so lines stay in order,
+    /// up to 1 MiB, further writes are dropped. All of it is dropped once the
+    /// attempts run out. Dropped writes are counted as failed.
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    Retry { attempts: u32, backoff_ms: u64 },
+    /// The output is written to the appender with the given id instead.
+    Failover(AppenderId),
+}
+impl ErrorPolicy {
+    #[cfg(feature = "serde")]
+    fn is_ignore(&self) -> bool {
+        matches!(self, Self::Ignore)
+    }
+}
+
+/// `Sample` keeps only a share of a logger's events at or below a level,
+/// more severe events are always kept. When both `rate` and `every` are given
+/// an event must pass both to be kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Sample {
+    /// The share of events kept at random, from `0.0` to `1.0`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub rate:  Option<Ppm>,
+    /// Keep one in every `every` events.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub every: Option<u32>,
+    /// The most severe level which is sampled, `DEBUG` by default.
+    #[cfg_attr(feature = "serde", serde(default = "Sample::default_level"))]
+    pub level: LevelFilter,
+}
+impl Sample {
+    #[must_use]
+    pub fn default_level() -> LevelFilter {
+        LevelFilter::DEBUG
+    }
+}
+
+/// A share in parts per million, written as a fraction from `0.0` to `1.0`.
+/// Kept as an integer so that configs can be compared for equality.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema), schemars(transparent))]
+pub struct Ppm(#[cfg_attr(feature = "schemars", schemars(with = "f64"))] pub u32);
+impl Ppm {
+    /// The whole.
+    pub const ONE: Self = Self(1_000_000);
+
+    /// The share as a fraction, at most `1.0`.
+    #[must_use]
+    pub fn fraction(self) -> f64 {
+        f64::from(self.0.min(Self::ONE.0)) / f64::from(Self::ONE.0)
+    }
+
+    /// The nearest share to `fraction`, which must be from `0.0` to `1.0`.
+    #[must_use]
+    pub fn from_fraction(fraction: f64) -> Option<Self> {
+        if !(0.0..=1.0).contains(&fraction) {
+            return None;
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // within 0..=ONE
+        let ppm = (fraction * f64::from(Self::ONE.0)).round() as u32;
+        Some(Self(ppm))
+    }
+}
+#[cfg(feature = "serde")]
+impl Serialize for Ppm {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f64(self.fraction())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Ppm {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let fraction = f64::deserialize(deserializer)?;
+        Self::from_fraction(fraction).ok_or_else(|| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Float(fraction),
+                &"a fraction from 0.0 to 1.0",
+            )
+        })
+    }
+}
+
+/// A `SyncPolicy` specifies how often an appender fsyncs its file, trading
+/// throughput for how much output a crash can lose.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
+pub enum SyncPolicy {
+    /// Leave it to the OS.
+    #[default]
+    Never,
+    /// After every event.
+    EveryEvent,
+    /// After every `n` events.
+    EveryEvents(u32),
+    /// On the first event at least this many milliseconds after the last
+    /// sync.
+    IntervalMs(u64),
+}
+impl SyncPolicy {
+    #[cfg(feature = "serde")]
+    fn is_never(&self) -> bool {
+        matches!(self, Self::Never)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[allow(clippy::trivially_copy_pass_by_ref)] // required by serde
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// `Integrity` makes an appender append a running SHA-256 hash to every line,
+/// so that edits to its files can be detected with `trace4rs::verify`.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Integrity {
+    /// A key turning the hash into an HMAC, so that the chain can't be
+    /// recomputed without it. Env variables are expanded with the following
+    /// syntax: `$ENV{var_name}`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub key: Option<String>,
+}
+
+/// `Dedupe` collapses consecutive repeats of an event written to an appender
+/// into a single "last message repeated N times" line, like syslogd does.
+/// Events repeat when they have the same callsite, level and field values,
+/// whatever their timestamps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Dedupe {
+    /// Repeats are only collapsed while they arrive within this many
+    /// milliseconds of the first line of the run.
+    pub window_ms: u64,
+}
+
 /// A Policy specifies how a `RollingFile` appender should be rolled.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
@@ -384,7 +1089,7 @@ impl Policy {
     ///
     /// # Errors
     /// If the size is not of the aforementioned form we will fail to parse.
-    pub fn calculate_maximum_file_size(size: &str) -> Result<u64> {
+    pub fn calculate_maximum_file_size(size: &str) -> result::Result<u64, Error> {
         const KB: u64 = 1024;
         const MB: u64 = KB * 1024;
         const GB: u64 = MB * 1024;
@@ -436,20 +1141,37 @@ impl Policy {
 
 #[cfg(all(test, feature = "serde"))]
 mod test {
-    use literally::hset;
-
     use super::{
+        AppenderId,
         LevelFilter,
         Logger,
+        Timezone,
     };
     use crate::config::Format;
 
+    #[test]
+    fn test_timezone_serde() {
+        for (s, tz) in [
+            ("local", Timezone::Local),
+            ("utc", Timezone::Utc),
+            ("+02:00", Timezone::Fixed(2 * 3600)),
+            ("-05:30", Timezone::Fixed(-(5 * 3600 + 30 * 60))),
+        ] {
+            let value = serde_json::Value::String(s.to_string());
+            assert_eq!(serde_json::from_value::<Timezone>(value.clone()).unwrap(), tz);
+            assert_eq!(serde_json::to_value(tz).unwrap(), value);
+        }
+        assert_eq!("+2".parse::<Timezone>().unwrap(), Timezone::Fixed(7200));
+        for bad in ["02:00", "+24:00", "+01:60", "cet", ""] {
+            assert!(bad.parse::<Timezone>().is_err(), "{bad}");
+        }
+    }
+
     #[test]
     fn test_format_serde() {
         let lgr = Logger {
-            appenders: hset! {},
-            level:     LevelFilter::OFF,
-            format:    Format::Normal,
+            format: Format::Normal,
+            ..Logger::new(LevelFilter::OFF, Vec::<AppenderId>::new())
         };
         let lgr_value = dbg!(serde_json::to_value(&lgr).unwrap());
         assert!(lgr_value.get("format").is_none());
@@ -457,9 +1179,8 @@ mod test {
         assert_eq!(lgr_parsed.format, Format::Normal);
 
         let lgr = Logger {
-            appenders: hset! {},
-            level:     LevelFilter::OFF,
-            format:    Format::MessageOnly,
+            format: Format::MessageOnly,
+            ..Logger::new(LevelFilter::OFF, Vec::<AppenderId>::new())
         };
         let lgr_value = dbg!(serde_json::to_value(&lgr).unwrap());
         let fmt = lgr_value.get("format").unwrap().as_str().unwrap();
@@ -468,14 +1189,23 @@ mod test {
         assert_eq!(lgr_parsed.format, Format::MessageOnly);
 
         let lgr = Logger {
-            appenders: hset! {},
-            level:     LevelFilter::OFF,
-            format:    Format::Custom("foobar".to_string()),
+            format: Format::Custom("foobar".to_string()),
+            ..Logger::new(LevelFilter::OFF, Vec::<AppenderId>::new())
         };
         let lgr_value = dbg!(serde_json::to_value(&lgr).unwrap());
         let fmt = lgr_value.get("format").unwrap().as_str().unwrap();
         assert_eq!(fmt, "foobar");
         let lgr_parsed: Logger = serde_json::from_value(lgr_value).unwrap();
         assert_eq!(lgr_parsed.format, Format::Custom("foobar".to_string()));
+
+        let lgr = Logger {
+            format: Format::Log4rsPattern("{d} {l} - {m}{n}".to_string()),
+            ..Logger::new(LevelFilter::OFF, Vec::<AppenderId>::new())
+        };
+        let lgr_value = dbg!(serde_json::to_value(&lgr).unwrap());
+        let fmt = lgr_value.get("format").unwrap();
+        assert_eq!(fmt, &serde_json::json!({ "log4rs": "{d} {l} - {m}{n}" }));
+        let lgr_parsed: Logger = serde_json::from_value(lgr_value).unwrap();
+        assert_eq!(lgr_parsed.format, lgr.format);
     }
 }
@@ -13,6 +13,9 @@ pub enum Error {
     #[error("Policy size overflow (byte size does not fit in u64): {number} {unit}")]
     Overflow { number: u64, unit: String },
 
+    #[error("Invalid timezone '{0}', expected 'local', 'utc' or an offset such as '+02:00'")]
+    InvalidTimezone(String),
+
     #[error("Failed to parse as an int from the config: {0}")]
     ParseIntError(#[from] ParseIntError),
 }
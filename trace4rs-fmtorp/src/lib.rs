@@ -2,149 +2,721 @@ use core::fmt;
 use std::{
     borrow::Cow,
     collections::HashSet,
-    ops::RangeInclusive,
+    ops::Range,
 };
 
 use tracing_subscriber::fmt::format;
+use unicode_width::UnicodeWidthStr;
+
+mod padding;
+
+pub use padding::{
+    Align,
+    Padding,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Illegal character {0}: {1}")]
     IllegalCharacter(char, String),
 
-    #[error("Unknown field: {0}")]
-    UnknownField(String),
+    #[error(
+        "Unknown field: {0}{}",
+        .1.as_ref().map(|s| format!(", did you mean '{s}'?")).unwrap_or_default()
+    )]
+    UnknownField(String, Option<String>),
 
     #[error("Empty field found")]
     EmptyField,
+
+    #[error("Invalid argument to field {field}: {reason}")]
+    InvalidArgument { field: String, reason: String },
+
+    #[error("Unclosed field")]
+    UnclosedField,
+
+    #[error("Unclosed conditional group")]
+    UnclosedGroup,
+
+    /// An error at a position in the format string.
+    #[error("{error} at byte {offset}\n{snippet}")]
+    At {
+        /// The byte offset of the error in the format string.
+        offset:  usize,
+        /// The line of the format string with the error underlined.
+        snippet: String,
+        error:   Box<Error>,
+    },
+}
+impl Error {
+    /// Locate `error` at `span` of `fmt_str`.
+    fn at(fmt_str: &str, span: Range<usize>, error: Self) -> Self {
+        Self::At {
+            offset:  span.start,
+            snippet: snippet(fmt_str, span),
+            error:   Box::new(error),
+        }
+    }
+
+    /// The byte offset in the format string of the error, if known.
+    #[must_use]
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Self::At { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The error without its position.
+    #[must_use]
+    pub fn inner(&self) -> &Self {
+        match self {
+            Self::At { error, .. } => error,
+            e => e,
+        }
+    }
+}
+
+/// The line of `fmt_str` holding `span`, with the span underlined by carets.
+fn snippet(fmt_str: &str, span: Range<usize>) -> String {
+    let line_start = fmt_str
+        .get(..span.start)
+        .and_then(|s| s.rfind('\n'))
+        .map_or(0, |i| i.saturating_add(1));
+    let line_end = fmt_str
+        .get(span.start..)
+        .and_then(|s| s.find('\n'))
+        .map_or(fmt_str.len(), |i| i.saturating_add(span.start));
+    let line = fmt_str.get(line_start..line_end).unwrap_or_default();
+    let indent = fmt_str.get(line_start..span.start).unwrap_or_default().width();
+    let carets = fmt_str
+        .get(span.start..span.end.min(line_end))
+        .unwrap_or_default()
+        .width()
+        .max(1);
+    format!("    {line}\n    {}{}", " ".repeat(indent), "^".repeat(carets))
+}
+
+/// A field of a format string, `{name}` or `{name:arg}`. A padding spec, as in
+/// `{name:<5}` or `{name:arg:<5}`, isn't part of the argument.
+///
+/// A field registered as `prefix.*` is parameterized: it matches
+/// `{prefix.param}` for any `param`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field<'a> {
+    /// The position of the field among the fields of the format string.
+    pub index: usize,
+    pub name:  &'static str,
+    /// The parameter of a parameterized field.
+    pub param: Option<&'a str>,
+    /// Everything after the first `:` and before the padding spec, if any.
+    pub arg:   Option<&'a str>,
+}
+
+/// A part of a parsed format string.
+#[derive(Debug)]
+enum Piece {
+    /// Text copied as is, a range indexing the format string.
+    Literal(Range<usize>),
+    /// The field of the given index.
+    Field(usize),
+    /// A conditional group `{?...}`, written only if one of its fields
+    /// writes something.
+    Group(Vec<Piece>),
+}
+
+/// Tracks whether anything non-empty was written.
+struct Tracking<'w> {
+    inner:   format::Writer<'w>,
+    written: bool,
+}
+impl fmt::Write for Tracking<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.written |= !s.is_empty();
+        self.inner.write_str(s)
+    }
 }
 
 #[derive(Debug)]
 pub struct Fmtr<'fmtstr> {
     /// The owned or static borrowed format string.
     fmt_str:      Cow<'fmtstr, str>,
-    /// The ranges indexing `fmt_str` which 1-1 index `ordered_fields`.
+    /// The format string split into literals, fields and groups.
     /// # Invariants
-    /// Ranges are strictly within bounds of fmt_str
-    field_ranges: Vec<RangeInclusive<usize>>,
-    /// The names of fields indexed identically to field_ranges.
+    /// Ranges are strictly within bounds of fmt_str and field indexes within
+    /// bounds of the field vecs.
+    pieces:       Vec<Piece>,
+    /// The names of fields in the order they appear.
     field_names:  Vec<&'static str>,
+    /// The ranges indexing `fmt_str` of the fields' arguments, indexed
+    /// identically to `field_names`.
+    field_args:   Vec<Option<Range<usize>>>,
+    /// The ranges indexing `fmt_str` of the parameters of parameterized
+    /// fields, indexed identically to `field_names`.
+    field_params: Vec<Option<Range<usize>>>,
+    /// The padding of the fields, indexed identically to `field_names`.
+    field_pads:   Vec<Option<Padding>>,
+    /// The ranges indexing `fmt_str` of the whole fields, braces included,
+    /// indexed identically to `field_names`.
+    field_spans:  Vec<Range<usize>>,
 }
 impl<'fmtstr> Fmtr<'fmtstr> {
     /// Unrecognized fields should be an error
+    ///
+    /// `{?...}` is a conditional group: its contents, text and fields, are
+    /// only written if at least one of its fields writes something, e.g.
+    /// `{?[{f}]}` leaves out the brackets when there are no fields. Groups
+    /// may be nested.
+    ///
+    /// Braces and backslashes are written as `\{`, `\}` and `\\`, or braces
+    /// as `{{` and `}}`. Inside a group `}` always closes it, so nested
+    /// groups may close together as in `{?a{?b{f.x}}}`, and a closing brace
+    /// there is written `\}`.
+    ///
+    /// A field may be followed by an argument and a padding spec, as in
+    /// `{name:arg:<5}`. A padding spec alone is written `{name:<5}`, so an
+    /// argument which reads as a spec itself, or ends in `:` and one, must be
+    /// followed by a spec or an empty one: `{name:5:}`.
     /// # Errors
-    /// We could encounter an illegal character or unknown field
+    /// We could encounter an illegal character, unknown field or unclosed
+    /// field or group, the error is located by `Error::At`.
     pub fn new(
         fmt_str: impl Into<Cow<'fmtstr, str>>,
         fields: &HashSet<&'static str>,
     ) -> Result<Self, Error> {
         let fmt_str = fmt_str.into();
+        let at = |span: Range<usize>, error| Error::at(&fmt_str, span, error);
         let mut start = None;
-        let mut in_escape = false;
-        let mut field_ranges: Vec<RangeInclusive<usize>> = Vec::with_capacity(fields.len());
+        // skip an escaped char or the `?` opening a group
+        let mut skip = false;
+        // where the current literal starts
+        let mut literal = 0;
+        // the pieces of the open groups with where they start, the whole
+        // format string at the bottom
+        let mut groups: Vec<(usize, Vec<Piece>)> = vec![(0, Vec::new())];
         let mut field_names: Vec<&'static str> = Vec::with_capacity(fields.len());
+        let mut field_args: Vec<Option<Range<usize>>> = Vec::with_capacity(fields.len());
+        let mut field_params: Vec<Option<Range<usize>>> = Vec::with_capacity(fields.len());
+        let mut field_pads: Vec<Option<Padding>> = Vec::with_capacity(fields.len());
+        let mut field_spans: Vec<Range<usize>> = Vec::with_capacity(fields.len());
 
         for (xi, x) in fmt_str.char_indices() {
+            if skip {
+                skip = false;
+                continue;
+            }
+            // no overflow potential, `xi` is the start of a char
+            #[allow(clippy::arithmetic_side_effects)]
+            let after = xi + x.len_utf8();
             // inside a field match
             if let Some(strt) = start {
-                // illegal chars
-                if x == '{' || x == '\\' {
-                    return Err(Error::IllegalCharacter(
-                        x,
-                        "found inside field name block".to_string(),
-                    ));
-                }
-                // end match
-                if x == '}' {
-                    #[allow(clippy::arithmetic_side_effects)] // no overflow potential
-                    if strt + 1 == xi {
-                        return Err(Error::EmptyField);
-                    }
-                    field_ranges.push(strt..=xi);
-                    // safe since we know the slice is non-empty and xi in bounds
-                    // and no overflow potential
-                    #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
-                    let ff = &fmt_str[(strt + 1)..xi];
-                    if let Some(f) = fields.get(ff) {
-                        field_names.push(*f);
-                        start = None;
-                    } else {
-                        return Err(Error::UnknownField(ff.to_string()));
-                    }
-                }
-            } else {
-                // match escape
-                if x == '\\' {
-                    in_escape = true;
-                    continue; // avoid the reset at bottom
-                }
-                // match unescaped brackets
-                if !in_escape {
-                    if x == '}' {
-                        return Err(Error::IllegalCharacter(
-                            x,
-                            "found outside field name block".to_string(),
+                match x {
+                    '{' | '\\' => {
+                        return Err(at(
+                            xi..after,
+                            Error::IllegalCharacter(x, "found inside field name block".to_string()),
                         ));
-                    } else if x == '{' {
-                        start = Some(xi);
-                    }
+                    },
+                    '}' => {
+                        let field = parse_field(&fmt_str, fields, strt, xi)?;
+                        field_names.push(field.name);
+                        field_params.push(field.param);
+                        field_args.push(field.arg);
+                        field_pads.push(field.pad);
+                        field_spans.push(strt..after);
+                        if let Some((_, group)) = groups.last_mut() {
+                            group.push(Piece::Field(field_names.len().saturating_sub(1)));
+                        }
+                        literal = after;
+                        start = None;
+                    },
+                    _ => {},
                 }
+                continue;
+            }
+            match (x, fmt_str.get(after..).and_then(|r| r.chars().next())) {
+                // escapes write the char after the first, but `}}` closes
+                // groups while one is open
+                ('\\', Some('{' | '}' | '\\')) | ('{', Some('{')) | ('}', Some('}'))
+                    if x != '}' || groups.len() == 1 =>
+                {
+                    push_literal(&mut groups, literal..xi);
+                    literal = after;
+                    skip = true;
+                },
+                ('{', Some('?')) => {
+                    push_literal(&mut groups, literal..xi);
+                    groups.push((xi, Vec::new()));
+                    literal = after.saturating_add(1);
+                    skip = true;
+                },
+                ('{', _) => {
+                    push_literal(&mut groups, literal..xi);
+                    start = Some(xi);
+                },
+                // closes the innermost group
+                ('}', _) if groups.len() > 1 => {
+                    push_literal(&mut groups, literal..xi);
+                    close_group(&mut groups);
+                    literal = after;
+                },
+                ('}', _) => {
+                    return Err(at(
+                        xi..after,
+                        Error::IllegalCharacter(x, "found outside field name block".to_string()),
+                    ));
+                },
+                _ => {},
             }
-            in_escape = false;
         }
+        if let Some(strt) = start {
+            return Err(at(strt..fmt_str.len(), Error::UnclosedField));
+        }
+        if let [_, .., (group_start, _)] = groups.as_slice() {
+            return Err(at(
+                *group_start..group_start.saturating_add(2),
+                Error::UnclosedGroup,
+            ));
+        }
+        push_literal(&mut groups, literal..fmt_str.len());
+        let pieces = groups.pop().map(|(_, pieces)| pieces).unwrap_or_default();
         Ok(Self {
             fmt_str,
-            field_ranges,
+            pieces,
             field_names,
+            field_args,
+            field_params,
+            field_pads,
+            field_spans,
         })
     }
 
+    /// Locate `error` at the field of index `i`, for errors found after
+    /// parsing such as invalid arguments.
+    #[must_use]
+    pub fn error_at(&self, i: usize, error: Error) -> Error {
+        match self.field_spans.get(i) {
+            Some(span) => Error::at(&self.fmt_str, span.clone(), error),
+            None => error,
+        }
+    }
+
     #[must_use]
     pub fn field_from_id(&self, i: usize) -> Option<&'static str> {
         self.field_names.get(i).copied()
     }
 
+    #[must_use]
+    pub fn field_arg_from_id(&self, i: usize) -> Option<&str> {
+        let range = self.field_args.get(i)?.clone()?;
+        self.fmt_str.get(range)
+    }
+
+    #[must_use]
+    pub fn field_param_from_id(&self, i: usize) -> Option<&str> {
+        let range = self.field_params.get(i)?.clone()?;
+        self.fmt_str.get(range)
+    }
+
+    /// The fields in the order they appear in the format string.
+    pub fn fields(&self) -> impl Iterator<Item = Field<'_>> {
+        self.field_names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                Field {
+                    index,
+                    name,
+                    param: self.field_param_from_id(index),
+                    arg: self.field_arg_from_id(index),
+                }
+            })
+    }
+
     /// # Errors
     /// If we fail to format the value a fmt Err will be returned
-    ///
-    /// # Panics
-    /// Panics should only happen on bugs.
-    #[allow(clippy::unwrap_in_result)]
     pub fn write(
         &self,
         mut writer: format::Writer<'_>,
         value_writer: &impl FieldValueWriter,
     ) -> fmt::Result {
-        let mut last = 0;
-        for (i, range) in self.field_ranges.iter().enumerate() {
-            // write everything from the last field to start of next
+        self.write_pieces(&self.pieces, writer.by_ref(), value_writer)?;
+        writeln!(writer)
+    }
 
-            // safe since last range.start is inbounds as invariant of Fmtr.
-            #[allow(clippy::indexing_slicing)]
-            write!(writer.by_ref(), "{}", &self.fmt_str[last..*range.start()])?;
+    /// Write `pieces`, returning whether any of their fields wrote something.
+    fn write_pieces(
+        &self,
+        pieces: &[Piece],
+        mut writer: format::Writer<'_>,
+        value_writer: &impl FieldValueWriter,
+    ) -> Result<bool, fmt::Error> {
+        let mut written = false;
+        for piece in pieces {
+            match piece {
+                Piece::Literal(range) => {
+                    writer.write_str(self.fmt_str.get(range.clone()).unwrap_or_default())?;
+                },
+                Piece::Field(i) => written |= self.write_field(*i, writer.by_ref(), value_writer)?,
+                Piece::Group(group) => {
+                    let mut buf = String::new();
+                    if self.write_pieces(group, format::Writer::new(&mut buf), value_writer)? {
+                        writer.write_str(&buf)?;
+                        written = true;
+                    }
+                },
+            }
+        }
+        Ok(written)
+    }
 
-            // unseparated
-            // unwrap ok since indexes coming from same vec
-            #[allow(clippy::unwrap_used)]
-            let field = self.field_from_id(i).unwrap();
+    /// Write the field of index `i`, returning whether its value is non-empty.
+    fn write_field(
+        &self,
+        i: usize,
+        writer: format::Writer<'_>,
+        value_writer: &impl FieldValueWriter,
+    ) -> Result<bool, fmt::Error> {
+        let Some(name) = self.field_from_id(i) else {
+            return Ok(false);
+        };
+        let field = Field {
+            index: i,
+            name,
+            param: self.field_param_from_id(i),
+            arg: self.field_arg_from_id(i),
+        };
+        if let Some(pad) = self.field_pads.get(i).copied().flatten() {
+            let mut value = String::new();
+            value_writer.write_value(format::Writer::new(&mut value), field)?;
+            pad.write(writer, &value)?;
+            Ok(!value.is_empty())
+        } else {
+            let mut tracking = Tracking {
+                inner:   writer,
+                written: false,
+            };
+            value_writer.write_value(format::Writer::new(&mut tracking), field)?;
+            Ok(tracking.written)
+        }
+    }
+}
 
-            value_writer.write_value(writer.by_ref(), field)?;
+/// Add the literal `range` to the innermost group, unless it is empty.
+fn push_literal(groups: &mut [(usize, Vec<Piece>)], range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    if let Some((_, group)) = groups.last_mut() {
+        group.push(Piece::Literal(range));
+    }
+}
 
-            // last may run off the end if last range
-            #[allow(clippy::arithmetic_side_effects)]
-            {
-                last = range.end() + 1;
+/// Move the innermost group into its parent.
+fn close_group(groups: &mut Vec<(usize, Vec<Piece>)>) {
+    if let Some((_, group)) = groups.pop() {
+        if let Some((_, parent)) = groups.last_mut() {
+            parent.push(Piece::Group(group));
+        }
+    }
+}
+
+/// A parsed field, the ranges index the format string.
+struct ParsedField {
+    name:  &'static str,
+    param: Option<Range<usize>>,
+    arg:   Option<Range<usize>>,
+    pad:   Option<Padding>,
+}
+
+/// Parse the field from the `{` at `start` to the `}` at `end`.
+fn parse_field(
+    fmt_str: &str,
+    fields: &HashSet<&'static str>,
+    start: usize,
+    end: usize,
+) -> Result<ParsedField, Error> {
+    let name_start = start.saturating_add(1);
+    if name_start == end {
+        return Err(Error::at(fmt_str, start..end.saturating_add(1), Error::EmptyField));
+    }
+    let ff = fmt_str.get(name_start..end).unwrap_or_default();
+    // the name ends at the first ':', the rest is its argument
+    let (ff, arg) = match ff.find(':') {
+        Some(i) => {
+            let arg_start = name_start.saturating_add(i).saturating_add(1);
+            (ff.get(..i).unwrap_or_default(), Some(arg_start..end))
+        },
+        None => (ff, None),
+    };
+    let Some((name, param)) = lookup(fields, ff, name_start) else {
+        return Err(Error::at(
+            fmt_str,
+            name_start..name_start.saturating_add(ff.len()),
+            Error::UnknownField(ff.to_string(), suggest(fields, ff)),
+        ));
+    };
+    let (arg, pad) = match arg {
+        Some(arg) => split_padding(fmt_str, arg),
+        None => (None, None),
+    };
+    Ok(ParsedField {
+        name,
+        param,
+        arg,
+        pad,
+    })
+}
+
+/// The known field closest to the unknown `name`, if one is close enough to
+/// be a typo.
+fn suggest(fields: &HashSet<&'static str>, name: &str) -> Option<String> {
+    let param = name.split_once('.').map(|(_, param)| param);
+    let mut candidates: Vec<String> = fields
+        .iter()
+        .filter_map(|f| {
+            match (f.strip_suffix(".*"), param) {
+                (Some(prefix), Some(param)) => Some(format!("{prefix}.{param}")),
+                (Some(_), None) => None,
+                (None, _) => Some((*f).to_string()),
             }
+        })
+        .collect();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .map(|c| (distance(name, &c), c))
+        .filter(|(d, _)| *d <= 2 && *d < name.chars().count())
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+#[allow(clippy::indexing_slicing)] // indexes within `0..=b.len()`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i.saturating_add(1);
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j.saturating_add(1)];
+            row[j.saturating_add(1)] = if ca == *cb {
+                diagonal
+            } else {
+                diagonal.min(above).min(row[j]).saturating_add(1)
+            };
+            diagonal = above;
         }
-        // safe since if we're off the end it will just be an empty slice
-        #[allow(clippy::indexing_slicing)]
-        write!(writer, "{}", &self.fmt_str[last..])?;
-        writeln!(writer)
+    }
+    row[b.len()]
+}
+
+/// Look up the field named `name`, which starts at `start` in the format
+/// string. A parameterized field also yields the range of its parameter.
+fn lookup(
+    fields: &HashSet<&'static str>,
+    name: &str,
+    start: usize,
+) -> Option<(&'static str, Option<Range<usize>>)> {
+    if let Some(f) = fields.get(name) {
+        return Some((f, None));
+    }
+    let (prefix, param) = name.split_once('.')?;
+    if param.is_empty() {
+        return None;
+    }
+    let f = fields.get(format!("{prefix}.*").as_str())?;
+    let param_start = start.saturating_add(prefix.len()).saturating_add(1);
+    Some((f, Some(param_start..start.saturating_add(name.len()))))
+}
+
+/// Split the padding spec off the argument of a field: either the whole
+/// argument or the part after its last `:`, which may be empty.
+fn split_padding(fmt_str: &str, arg: Range<usize>) -> (Option<Range<usize>>, Option<Padding>) {
+    let Some(s) = fmt_str.get(arg.clone()) else {
+        return (Some(arg), None);
+    };
+    if let Some(pad) = Padding::parse(s) {
+        return (None, Some(pad));
+    }
+    if let Some(arg_str) = s.strip_suffix(':') {
+        let arg = arg.start..arg.start.saturating_add(arg_str.len());
+        return ((!arg.is_empty()).then_some(arg), None);
+    }
+    let split = s.rfind(':').and_then(|i| {
+        let pad = Padding::parse(s.get(i.saturating_add(1)..)?)?;
+        Some((i, pad))
+    });
+    match split {
+        Some((0, pad)) => (None, Some(pad)),
+        Some((i, pad)) => (Some(arg.start..arg.start.saturating_add(i)), Some(pad)),
+        None => (Some(arg), None),
     }
 }
 
 pub trait FieldValueWriter {
     /// # Errors
     /// If we fail to format the value a fmt Err will be returned
-    fn write_value(&self, writer: format::Writer<'_>, field: &'static str) -> fmt::Result;
+    fn write_value(&self, writer: format::Writer<'_>, field: Field<'_>) -> fmt::Result;
+}
+
+#[cfg(test)]
+mod test {
+    use core::fmt;
+    use std::collections::{
+        HashMap,
+        HashSet,
+    };
+
+    use tracing_subscriber::fmt::format;
+
+    use super::{
+        Error,
+        Field,
+        FieldValueWriter,
+        Fmtr,
+        Padding,
+    };
+
+    fn known() -> HashSet<&'static str> {
+        HashSet::from(["m", "l", "t", "f.*"])
+    }
+
+    /// Writes the values of `{name}` and `{prefix.param}` fields by name.
+    struct Values<'v>(HashMap<&'v str, &'v str>);
+    impl FieldValueWriter for Values<'_> {
+        fn write_value(&self, mut writer: format::Writer<'_>, field: Field<'_>) -> fmt::Result {
+            let name = match field.param {
+                Some(param) => format!("{}{param}", field.name.trim_end_matches('*')),
+                None => field.name.to_string(),
+            };
+            writer.write_str(self.0.get(name.as_str()).copied().unwrap_or_default())
+        }
+    }
+
+    /// `fmt_str` written with `values`.
+    fn write(fmt_str: &str, values: &[(&str, &str)]) -> String {
+        let fmtr = Fmtr::new(fmt_str, &known()).unwrap();
+        let mut out = String::new();
+        fmtr.write(
+            format::Writer::new(&mut out),
+            &Values(values.iter().copied().collect()),
+        )
+        .unwrap();
+        out
+    }
+
+    /// The argument and padding of the only field of `fmt_str`.
+    fn split(fmt_str: &str) -> (Option<String>, Option<Padding>) {
+        let fmtr = Fmtr::new(fmt_str, &known()).unwrap();
+        let fields: Vec<_> = fmtr.fields().collect();
+        let [field] = fields.as_slice() else {
+            panic!("expected one field in {fmt_str}");
+        };
+        let pad = fmtr.field_pads.first().copied().flatten();
+        (field.arg.map(str::to_string), pad)
+    }
+
+    #[test]
+    fn diagnostics() {
+        let err = Fmtr::new("{m} {lvl}", &known()).unwrap_err();
+        assert_eq!(err.offset(), Some(5));
+        assert_eq!(
+            err.to_string(),
+            "Unknown field: lvl, did you mean 'l'? at byte 5\n    {m} {lvl}\n         ^^^"
+        );
+        let suggested = Fmtr::new("{m} {fx.user}", &known()).unwrap_err();
+        assert!(suggested
+            .to_string()
+            .starts_with("Unknown field: fx.user, did you mean 'f.user'?"));
+        let unclosed = Fmtr::new("{m} {l", &known()).unwrap_err();
+        assert!(matches!(unclosed.inner(), Error::UnclosedField));
+        assert_eq!(unclosed.offset(), Some(4));
+        let group = Fmtr::new("{m}{? [{l}]", &known()).unwrap_err();
+        assert!(matches!(group.inner(), Error::UnclosedGroup));
+        assert_eq!(group.offset(), Some(3));
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            write(r"{{{m}}} \{x\} a\\b c\d", &[("m", "hi")]),
+            "{hi} {x} a\\b c\\d\n"
+        );
+    }
+
+    #[test]
+    fn conditional_groups() {
+        let fmt_str = "{m}{? [user={f.user}{?, try {f.attempt}}]}";
+        assert_eq!(write(fmt_str, &[("m", "anonymous")]), "anonymous\n");
+        assert_eq!(
+            write(fmt_str, &[("m", "login"), ("f.user", "bob")]),
+            "login [user=bob]\n"
+        );
+        assert_eq!(
+            write(fmt_str, &[
+                ("m", "retry"),
+                ("f.user", "amy"),
+                ("f.attempt", "2")
+            ]),
+            "retry [user=amy, try 2]\n"
+        );
+    }
+
+    #[test]
+    fn nested_groups_close_together() {
+        let literals = "{?a{?b{f.x}}}";
+        assert_eq!(write(literals, &[]), "\n");
+        assert_eq!(write(literals, &[("f.x", "1")]), "ab1\n");
+
+        let fields = "{?{f.a}{?{f.b}}}";
+        assert_eq!(write(fields, &[("f.b", "2")]), "2\n");
+        assert_eq!(write(fields, &[("f.a", "1"), ("f.b", "2")]), "12\n");
+        // a closing brace inside a group is escaped
+        assert_eq!(write(r"{?\}{f.a}}}}", &[("f.a", "1")]), "}1}\n");
+    }
+
+    #[test]
+    fn padding() {
+        let fmt_str = "{l:<5}|{l:>6}|{t:.8}|{m:*^9}|{m:.3}";
+        assert_eq!(
+            write(fmt_str, &[
+                ("l", "INFO"),
+                ("t", "trace4rs::padding"),
+                ("m", "héllo")
+            ]),
+            "INFO |  INFO|trace4rs|**héllo**|hél\n"
+        );
+        // wide characters count double
+        assert_eq!(
+            write(fmt_str, &[("l", "WARN"), ("t", "short"), ("m", "日本")]),
+            "WARN |  WARN|short|**日本***|日\n"
+        );
+    }
+
+    #[test]
+    fn arguments_and_padding() {
+        let width = |width: usize| Padding::parse(&width.to_string());
+
+        assert_eq!(split("{f.count:default=0}"), (Some("default=0".to_string()), None));
+        assert_eq!(split("{f.count:default=0:>3}"), (
+            Some("default=0".to_string()),
+            Padding::parse(">3")
+        ));
+        // a number alone is a width
+        assert_eq!(split("{f.count:0}"), (None, width(0)));
+        assert_eq!(split("{f.shard:5}"), (None, width(5)));
+        // unless an empty spec follows
+        assert_eq!(split("{f.shard:5:}"), (Some("5".to_string()), None));
+        assert_eq!(split("{f.host:default=a:80:}"), (
+            Some("default=a:80".to_string()),
+            None
+        ));
+        assert_eq!(split("{f.host:default=a:80}"), (
+            Some("default=a".to_string()),
+            width(80)
+        ));
+        assert_eq!(split("{f.x:}"), (Some(String::new()), None));
+    }
 }
@@ -0,0 +1,167 @@
+use core::fmt;
+
+use tracing_subscriber::fmt::format;
+use unicode_width::{
+    UnicodeWidthChar,
+    UnicodeWidthStr,
+};
+
+/// How a value is placed within its width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// The `[[fill]align][width][.max_width]` spec of a field, e.g. `{l:<5}` or
+/// `{t:.30}`. Widths are in terminal columns, so wide characters count double.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Padding {
+    pub fill:      char,
+    pub align:     Align,
+    /// The value is padded with `fill` up to this width.
+    pub width:     usize,
+    /// The value is cut off after this width.
+    pub max_width: Option<usize>,
+}
+
+impl Padding {
+    /// Parse a spec, `None` if `spec` isn't one.
+    #[must_use]
+    pub fn parse(spec: &str) -> Option<Self> {
+        let align_of = |c| match c {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        };
+        let mut chars = spec.chars();
+        let first = chars.next()?;
+        let second = chars.next();
+        let (fill, align, rest) = match (align_of(first), second.and_then(align_of)) {
+            (_, Some(align)) => (first, align, chars.as_str()),
+            (Some(align), None) => (' ', align, spec.get(first.len_utf8()..)?),
+            (None, None) => (' ', Align::Left, spec),
+        };
+        let (width, max_width) = match rest.split_once('.') {
+            Some((width, max)) => (width, Some(number(max)?)),
+            None => (rest, None),
+        };
+        let width = if width.is_empty() { 0 } else { number(width)? };
+        Some(Self {
+            fill,
+            align,
+            width,
+            max_width,
+        })
+    }
+
+    /// Write `value` cut off at the maximum width and padded to the width.
+    ///
+    /// # Errors
+    /// If we fail to write a fmt Err will be returned
+    pub fn write(&self, mut writer: format::Writer<'_>, value: &str) -> fmt::Result {
+        let value = self.max_width.map_or(value, |max| truncate(value, max));
+        let pad = self.width.saturating_sub(value.width());
+        let (before, after) = match self.align {
+            Align::Left => (0, pad),
+            Align::Right => (pad, 0),
+            Align::Center => (pad / 2, pad.saturating_sub(pad / 2)),
+        };
+        for _ in 0..before {
+            writer.write_char(self.fill)?;
+        }
+        writer.write_str(value)?;
+        for _ in 0..after {
+            writer.write_char(self.fill)?;
+        }
+        Ok(())
+    }
+}
+
+fn number(s: &str) -> Option<usize> {
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// The longest prefix of `value` at most `max` columns wide.
+fn truncate(value: &str, max: usize) -> &str {
+    let mut width = 0_usize;
+    for (i, c) in value.char_indices() {
+        width = width.saturating_add(c.width().unwrap_or(0));
+        if width > max {
+            return value.get(..i).unwrap_or_default();
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::fmt::format;
+
+    use super::{
+        Align,
+        Padding,
+    };
+
+    fn padded(spec: &str, value: &str) -> String {
+        let mut out = String::new();
+        Padding::parse(spec)
+            .unwrap()
+            .write(format::Writer::new(&mut out), value)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Padding::parse("*^9"),
+            Some(Padding {
+                fill:      '*',
+                align:     Align::Center,
+                width:     9,
+                max_width: None,
+            })
+        );
+        assert_eq!(
+            Padding::parse(".3"),
+            Some(Padding {
+                fill:      ' ',
+                align:     Align::Left,
+                width:     0,
+                max_width: Some(3),
+            })
+        );
+        assert_eq!(
+            Padding::parse(">"),
+            Some(Padding {
+                fill:      ' ',
+                align:     Align::Right,
+                width:     0,
+                max_width: None,
+            })
+        );
+        assert_eq!(Padding::parse(""), None);
+        assert_eq!(Padding::parse("-"), None);
+        assert_eq!(Padding::parse("+5"), None);
+        assert_eq!(Padding::parse("5.x"), None);
+    }
+
+    #[test]
+    fn write() {
+        assert_eq!(padded("<5", "ab"), "ab   ");
+        assert_eq!(padded(">5", "ab"), "   ab");
+        assert_eq!(padded("-^6", "ab"), "--ab--");
+        assert_eq!(padded("*^5", "ab"), "*ab**");
+        assert_eq!(padded("3", "abcd"), "abcd");
+        assert_eq!(padded("<5.2", "abcd"), "ab   ");
+        // widths are in columns
+        assert_eq!(padded("<5", "日本"), "日本 ");
+        assert_eq!(padded(".3", "日本"), "日");
+    }
+}
@@ -38,53 +38,60 @@ fn bench_appenders(c: &mut Criterion) {
     c.bench_function("tracing_rolling_file", |b| {
         b.iter(|| do_log!(target: "rolling_file", "foobar"))
     });
+    // several loggers matching each event
+    c.bench_function("tracing_multi_logger_same_format", |b| {
+        b.iter(|| do_log!(target: "fan::same", "foobar"));
+    });
+    c.bench_function("tracing_multi_logger_mixed_format", |b| {
+        b.iter(|| do_log!(target: "fan::mixed", "foobar"));
+    });
 }
 
 fn mk_handle() -> (Handle, impl Subscriber) {
     let appenders = {
         let console = config::Appender::console();
-        let file = config::Appender::File {
-            path: "file.log".to_string(),
-        };
-        let rolling_file = config::Appender::RollingFile {
-            path:   "rolling_file.log".to_string(),
-            policy: Policy {
-                pattern: Some("rolling_file.log.{}".to_string()),
-                max_size_roll_backups: 3,
-                maximum_file_size: "1mb".to_string(),
-            },
-        };
+        let file = config::Appender::file("file.log");
+        let rolling_file = config::Appender::rolling_file("rolling_file.log", Policy {
+            pattern: Some("rolling_file.log.{}".to_string()),
+            max_size_roll_backups: 3,
+            maximum_file_size: "1mb".to_string(),
+        });
 
         literally::hmap! {
             "console" => console,
             "file" => file,
             "rolling_file" => rolling_file,
+            "fan1" => config::Appender::file("fan1.log"),
+            "fan2" => config::Appender::file("fan2.log"),
+            "fan3" => config::Appender::file("fan3.log"),
         }
     };
 
-    let default = config::Logger {
-        level:     config::LevelFilter::INFO,
-        appenders: literally::hset! {"console"},
-        format:    Format::default(),
-    };
+    let default = config::Logger::new(config::LevelFilter::INFO, ["console"]);
     let loggers = {
-        let file_logger = config::Logger {
-            level:     config::LevelFilter::INFO,
-            appenders: literally::hset! {"file"},
-            format:    Format::default(),
-        };
-        let rolling_file_logger = config::Logger {
-            level:     config::LevelFilter::INFO,
-            appenders: literally::hset! {"file"},
-            format:    Format::default(),
+        let file_logger = config::Logger::new(config::LevelFilter::INFO, ["file"]);
+        let rolling_file_logger = config::Logger::new(config::LevelFilter::INFO, ["file"]);
+        let fan_logger = |appender: &str, format: Format| {
+            let mut logger = config::Logger::new(config::LevelFilter::INFO, [appender]);
+            logger.format = format;
+            logger
         };
-        literally::hmap! {"file" => file_logger, "rolling_file" => rolling_file_logger}
+        literally::hmap! {
+            "file" => file_logger,
+            "rolling_file" => rolling_file_logger,
+            "fan" => fan_logger("fan1", Format::default()),
+            "fan::same" => fan_logger("fan2", Format::default()),
+            "fan::mixed" => fan_logger("fan3", Format::MessageOnly),
+        }
     };
 
     let config = Config {
         default,
         loggers,
         appenders,
+        spans: literally::hmap! {},
+        context: literally::hmap! {},
+        redact: vec![],
     };
 
     Handle::from_config(&config).unwrap()
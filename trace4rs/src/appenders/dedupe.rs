@@ -0,0 +1,112 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    io,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use super::write_all_with;
+
+thread_local! {
+    /// The comparison key of the event being written on this thread.
+    static EVENT_KEY: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// Compare the lines written by `f` on `key`, which identifies the event
+/// they were formatted from, rather than on the lines themselves.
+pub fn with_event_key<R>(key: Option<Vec<u8>>, f: impl FnOnce() -> R) -> R {
+    if key.is_none() {
+        return f();
+    }
+    let prev = EVENT_KEY.with(|k| k.replace(key));
+    let res = f();
+    EVENT_KEY.with(|k| k.replace(prev));
+    res
+}
+
+/// `Dedupe` collapses consecutive repeats of a line into a single summary
+/// line, like syslogd's "last message repeated N times".
+///
+/// Lines written for an event are compared on the event's key, see
+/// `with_event_key`, so that repeats are found whatever the format puts
+/// around the message, such as a timestamp. Other lines are compared whole.
+#[derive(Debug)]
+pub struct Dedupe {
+    window:    Duration,
+    /// The comparison key of the last line which was actually written.
+    last:      Vec<u8>,
+    run_start: Instant,
+    repeated:  u64,
+}
+impl Dedupe {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last: Vec::new(),
+            run_start: Instant::now(),
+            repeated: 0,
+        }
+    }
+
+    /// Write `buf` through `write`, unless it repeats the previous line. The
+    /// whole of `buf` is reported as written either way.
+    pub fn write(
+        &mut self,
+        buf: &[u8],
+        mut write: impl FnMut(&[u8]) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        let out = self.filter(buf);
+        write_all_with(&out, &mut write)?;
+        Ok(buf.len())
+    }
+
+    /// Write out the summary of the current run, if anything was collapsed.
+    pub fn flush(&mut self, mut write: impl FnMut(&[u8]) -> io::Result<usize>) -> io::Result<()> {
+        match self.take_summary() {
+            Some(summary) => write_all_with(&summary, &mut write),
+            None => Ok(()),
+        }
+    }
+
+    /// What should actually be written for `buf`: nothing if it continues the
+    /// current run, otherwise the summary of the previous run followed by
+    /// `buf`.
+    fn filter<'b>(&mut self, buf: &'b [u8]) -> Cow<'b, [u8]> {
+        let now = Instant::now();
+        let repeats = EVENT_KEY.with(|k| {
+            let key = k.borrow();
+            let key = key.as_deref().unwrap_or(buf);
+            let repeats = key == self.last.as_slice();
+            if !repeats {
+                self.last.clear();
+                self.last.extend_from_slice(key);
+            }
+            repeats
+        });
+
+        if repeats && now.duration_since(self.run_start) < self.window {
+            self.repeated = self.repeated.saturating_add(1);
+            return Cow::Borrowed(&[]);
+        }
+        self.run_start = now;
+
+        match self.take_summary() {
+            Some(mut summary) => {
+                summary.extend_from_slice(buf);
+                Cow::Owned(summary)
+            },
+            None => Cow::Borrowed(buf),
+        }
+    }
+
+    fn take_summary(&mut self) -> Option<Vec<u8>> {
+        if self.repeated == 0 {
+            return None;
+        }
+        let n = std::mem::take(&mut self.repeated);
+        Some(format!("last message repeated {n} times\n").into_bytes())
+    }
+}
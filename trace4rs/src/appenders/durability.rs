@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    io::{
+        self,
+        LineWriter,
+        Write,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crate::config::SyncPolicy;
+
+/// `Durability` tracks when an appender's file is due an fsync according to
+/// its `SyncPolicy`, and in audit mode holds on to failures until the next
+/// flush.
+#[derive(Debug, Default)]
+pub struct Durability {
+    policy:    SyncPolicy,
+    audit:     bool,
+    /// Events written since the last sync.
+    unsynced:  u32,
+    last_sync: Option<Instant>,
+    failure:   Option<io::Error>,
+}
+impl Durability {
+    pub fn new(policy: SyncPolicy, audit: bool) -> Self {
+        Self {
+            policy,
+            audit,
+            ..Self::default()
+        }
+    }
+
+    /// Record that an event was written, returning whether it is time to
+    /// sync.
+    pub fn wrote(&mut self) -> bool {
+        self.unsynced = self.unsynced.saturating_add(1);
+        match &self.policy {
+            SyncPolicy::Never => false,
+            SyncPolicy::EveryEvent => true,
+            SyncPolicy::EveryEvents(n) => self.unsynced >= *n,
+            SyncPolicy::IntervalMs(ms) => {
+                self.last_sync
+                    .map_or(true, |t| t.elapsed() >= Duration::from_millis(*ms))
+            },
+        }
+    }
+
+    /// Flush `writer` and fsync its file.
+    pub fn sync(&mut self, writer: &mut LineWriter<fs::File>) -> io::Result<()> {
+        writer.flush()?;
+        writer.get_ref().sync_data()?;
+        self.unsynced = 0;
+        self.last_sync = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Whether rolls must sync the file and the renames, which they only do
+    /// if the appender syncs at all.
+    pub fn is_durable(&self) -> bool {
+        self.audit || !matches!(self.policy, SyncPolicy::Never)
+    }
+
+    /// Whether a flush must also sync.
+    pub fn is_audit(&self) -> bool {
+        self.audit
+    }
+
+    /// Keep `err` for the next flush in audit mode.
+    pub fn failed(&mut self, err: &io::Error) {
+        if self.audit && self.failure.is_none() {
+            self.failure = Some(io::Error::new(err.kind(), err.to_string()));
+        }
+    }
+
+    /// The first failure since the last flush, if any.
+    pub fn take_failure(&mut self) -> Option<io::Error> {
+        self.failure.take()
+    }
+}
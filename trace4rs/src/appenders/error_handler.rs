@@ -0,0 +1,234 @@
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    sync::atomic::{
+        AtomicBool,
+        AtomicU64,
+        Ordering,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use camino::Utf8Path;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+
+use super::Appender;
+use crate::config::ErrorPolicy;
+
+/// The most output held for a retry under `ErrorPolicy::Retry`, writes which
+/// would go over it are dropped.
+const MAX_HELD: usize = 1024 * 1024;
+
+/// `ErrorHandler` applies an appender's `ErrorPolicy` to failed writes and
+/// keeps count of them.
+#[derive(Debug, Default)]
+pub struct ErrorHandler {
+    policy:   ErrorPolicy,
+    count:    AtomicU64,
+    reported: AtomicBool,
+    /// The appender resolved from `ErrorPolicy::Failover`, set once all the
+    /// appenders of a config have been built.
+    failover: OnceCell<Appender>,
+    /// Output waiting for its next attempt under `ErrorPolicy::Retry`.
+    held:     Mutex<Option<Held>>,
+}
+impl ErrorHandler {
+    pub fn new(policy: ErrorPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    pub fn policy(&self) -> &ErrorPolicy {
+        &self.policy
+    }
+
+    /// The number of writes which have failed so far.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Count a failure the policy can't act on, such as a failed fsync.
+    pub fn record(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Apply the policy to a failure other than a write, such as taking the
+    /// lock of a shared file. It is counted, and reported under `ReportOnce`,
+    /// there being no output to retry or fail over.
+    pub fn failed(&self, err: &io::Error, path: &Utf8Path) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if self.policy == ErrorPolicy::ReportOnce {
+            self.report_once(err, path);
+        }
+    }
+
+    fn report_once(&self, err: &io::Error, path: &Utf8Path) {
+        if !self.reported.swap(true, Ordering::Relaxed) {
+            #[allow(clippy::print_stderr)] // necessary error surfacing
+            {
+                eprintln!(
+                    "trace4rs: Failed to write to '{path}': {err}, further errors will not be \
+                     reported"
+                );
+            }
+        }
+    }
+
+    /// Set the appender to write to if the policy is to fail over.
+    pub fn set_failover(&self, appender: Appender) {
+        let _ = self.failover.set(appender);
+    }
+
+    /// Apply the policy to the failed write of `buf` to `path`. Unless it
+    /// fails over, `buf` is reported as written: it is either dropped or held
+    /// for a retry.
+    ///
+    /// # Errors
+    /// If writing to the failover appender fails too.
+    pub fn handle(&self, err: io::Error, buf: &[u8], path: &Utf8Path) -> io::Result<usize> {
+        self.count.fetch_add(1, Ordering::Relaxed);
+
+        match &self.policy {
+            ErrorPolicy::Ignore => Ok(buf.len()),
+            ErrorPolicy::ReportOnce => {
+                self.report_once(&err, path);
+                Ok(buf.len())
+            },
+            ErrorPolicy::Retry {
+                attempts,
+                backoff_ms,
+            } => {
+                if *attempts > 0 {
+                    *self.held.lock() = Some(Held::new(
+                        buf.to_vec(),
+                        *attempts,
+                        Duration::from_millis(*backoff_ms),
+                    ));
+                }
+                Ok(buf.len())
+            },
+            ErrorPolicy::Failover(_) => {
+                match self.failover.get() {
+                    Some(appender) => appender.clone().write_all(buf).map(|()| buf.len()),
+                    None => Err(err),
+                }
+            },
+        }
+    }
+
+    /// Queue `buf` behind the output held for a retry, if there is any, so
+    /// that lines stay in order. Returns whether it was taken: queued, or
+    /// dropped and counted as a failed write if the held output would go over
+    /// `MAX_HELD`.
+    pub fn hold(&self, buf: &[u8]) -> bool {
+        let mut guard = self.held.lock();
+        let Some(held) = guard.as_mut() else {
+            return false;
+        };
+        if held.buf.len().saturating_add(buf.len()) > MAX_HELD {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        } else {
+            held.buf.extend_from_slice(buf);
+            held.writes = held.writes.saturating_add(1);
+        }
+        true
+    }
+
+    /// Attempt to write the output held for a retry with `write`, once its
+    /// backoff has passed or straight away if `now`. This never waits, the
+    /// attempt is left to the next write or flush instead.
+    ///
+    /// # Errors
+    /// The last error once the attempts have run out, the held output is
+    /// dropped and the writes queued behind the failed one are counted as
+    /// failed too.
+    pub fn retry_held(
+        &self,
+        now: bool,
+        mut write: impl FnMut(&[u8]) -> io::Result<usize>,
+    ) -> io::Result<()> {
+        let mut guard = self.held.lock();
+        let Some(held) = guard.as_mut() else {
+            return Ok(());
+        };
+        if !now && Instant::now() < held.due {
+            return Ok(());
+        }
+        match held.write_out(&mut write) {
+            Ok(()) => {
+                *guard = None;
+                Ok(())
+            },
+            Err(e) if held.attempts <= 1 => {
+                // the write which failed first is already counted
+                let lost = held.writes;
+                self.count
+                    .fetch_add(lost.saturating_sub(1), Ordering::Relaxed);
+                *guard = None;
+                Err(io::Error::new(
+                    e.kind(),
+                    format!("{e}, dropped the {lost} writes held for a retry"),
+                ))
+            },
+            Err(_) => {
+                held.next_attempt();
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Output held back by `ErrorPolicy::Retry` until its next attempt.
+#[derive(Debug)]
+struct Held {
+    buf:      Vec<u8>,
+    /// The number of writes `buf` holds.
+    writes:   u64,
+    /// The attempts left.
+    attempts: u32,
+    backoff:  Duration,
+    due:      Instant,
+}
+impl Held {
+    fn new(buf: Vec<u8>, attempts: u32, backoff: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            buf,
+            writes: 1,
+            attempts,
+            backoff,
+            due: now.checked_add(backoff).unwrap_or(now),
+        }
+    }
+
+    fn next_attempt(&mut self) {
+        self.attempts = self.attempts.saturating_sub(1);
+        self.backoff = self.backoff.saturating_mul(2);
+        let now = Instant::now();
+        self.due = now.checked_add(self.backoff).unwrap_or(now);
+    }
+
+    /// Write out the held output, whatever `write` took is no longer held
+    /// even if it fails part way.
+    fn write_out(&mut self, write: &mut impl FnMut(&[u8]) -> io::Result<usize>) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            match write(&self.buf) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.buf.drain(..n.min(self.buf.len()));
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    mem,
+    sync::Arc,
+};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use super::Appender;
+
+/// `FanOut` delivers each event to a set of appenders. The event is gathered
+/// into a buffer first and handed to each appender in a single write, so the
+/// lines of concurrent events never interleave however many writes formatting
+/// took.
+#[derive(Clone, Debug)]
+pub struct FanOut {
+    appenders: Arc<[Appender]>,
+}
+impl FanOut {
+    pub fn new(appenders: Vec<Appender>) -> Self {
+        Self {
+            appenders: appenders.into(),
+        }
+    }
+
+    /// Write a formatted event to each appender in a single write. Every
+    /// appender gets the event even if an earlier one failed, the first
+    /// failure is returned.
+    pub fn write_event(&self, buf: &[u8]) -> io::Result<()> {
+        let mut res = Ok(());
+        for appender in self.appenders.iter() {
+            let written = appender.clone().write_all(buf);
+            if res.is_ok() {
+                res = written;
+            }
+        }
+        res
+    }
+}
+impl<'a> MakeWriter<'a> for FanOut {
+    type Writer = EventWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        EventWriter {
+            fanout: self.clone(),
+            buf:    Vec::new(),
+        }
+    }
+}
+
+/// The writer for a single event, it is delivered when the writer is dropped
+/// or flushed.
+#[derive(Debug)]
+pub struct EventWriter {
+    fanout: FanOut,
+    buf:    Vec<u8>,
+}
+impl EventWriter {
+    fn deliver(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let buf = mem::take(&mut self.buf);
+        self.fanout.write_event(&buf)
+    }
+}
+impl io::Write for EventWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deliver()
+    }
+}
+impl Drop for EventWriter {
+    fn drop(&mut self) {
+        // failures are counted by the appenders' error handlers
+        let _ = self.deliver();
+    }
+}
@@ -0,0 +1,340 @@
+//! Tamper-evident log files.
+//!
+//! With `integrity` configured an appender appends a running hash to every
+//! line it writes:
+//!
+//! ```text
+//! <line> #chain:<64 hex digits>
+//! ```
+//!
+//! The hash of a line is `SHA-256(previous hash || line)`, or
+//! `HMAC-SHA-256(key, previous hash || line)` when a key is configured. The
+//! chain is carried across rolls, so the files of a rolling appender verify as
+//! one sequence, oldest first.
+//!
+//! Each file starts with an anchor, a sealed line holding the hash the chain
+//! continues from:
+//!
+//! ```text
+//! #chain-anchor:<64 hex digits> #chain:<64 hex digits>
+//! ```
+//!
+//! It gives the verifier a checked starting point once older files have been
+//! deleted by rolling.
+use std::{
+    fs,
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        Read,
+        Seek,
+        SeekFrom,
+    },
+};
+
+use camino::{
+    Utf8Path,
+    Utf8PathBuf,
+};
+use hmac::{
+    Hmac,
+    Mac,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+
+use crate::{
+    config,
+    env::expand_env_vars,
+};
+
+type Hash = [u8; 32];
+
+const MARKER: &[u8] = b" #chain:";
+const ANCHOR: &[u8] = b"#chain-anchor:";
+const HEX_LEN: usize = 64;
+/// The length of the suffix appended to each line, excluding the newline.
+const SUFFIX_LEN: usize = MARKER.len() + HEX_LEN;
+const SEALED_TAIL_LEN: usize = SUFFIX_LEN + 1;
+
+/// `Chain` seals the lines written to an appender.
+#[derive(Debug)]
+pub struct Chain {
+    key:     Option<Vec<u8>>,
+    state:   Hash,
+    /// The start of a line whose newline hasn't been written yet.
+    partial: Vec<u8>,
+    /// The file the active file is rolled to, where the chain continues from
+    /// while the active file is empty.
+    backup:  Option<Utf8PathBuf>,
+    /// Whether the next line starts a file, and so goes after an anchor.
+    anchor:  bool,
+}
+impl Chain {
+    /// Start a chain, resuming from the last line of the file at `path` if it
+    /// was sealed, or of the newest `backup` if the file is empty.
+    pub fn new(
+        integrity: &config::Integrity,
+        path: &Utf8Path,
+        backup: Option<Utf8PathBuf>,
+    ) -> Self {
+        let key = integrity
+            .key
+            .as_deref()
+            .map(|k| expand_env_vars(k).into_owned().into_bytes());
+        let mut chain = Self {
+            key,
+            state: Hash::default(),
+            partial: Vec::new(),
+            backup,
+            anchor: false,
+        };
+        chain.resume(path);
+        chain
+    }
+
+    /// Continue from the last line of the file at `path`, or of the newest
+    /// backup if the file is empty. Another process may have appended to
+    /// either, or the file may have been rolled or replaced.
+    pub fn resume(&mut self, path: &Utf8Path) {
+        let is_empty = fs::metadata(path).map_or(true, |m| m.len() == 0);
+        self.anchor = is_empty;
+        if !self.partial.is_empty() {
+            return;
+        }
+        let last = match &self.backup {
+            Some(backup) if is_empty => last_hash(backup),
+            _ => last_hash(path),
+        };
+        if let Some(hash) = last {
+            self.state = hash;
+        }
+    }
+
+    /// Start the next line with an anchor if the file at `path`, which has
+    /// just been replaced, is empty. The chain carries on from our own last
+    /// line, wherever it went.
+    pub fn reopened(&mut self, path: &Utf8Path) {
+        self.anchor = fs::metadata(path).map_or(true, |m| m.len() == 0);
+    }
+
+    /// Append the running hash to each complete line of `buf` and pass it to
+    /// `write`. The chain is rewound past a line which was dropped, so that
+    /// the next line links to the last one in the file. A trailing partial
+    /// line is held back until its newline is written.
+    ///
+    /// # Errors
+    /// The first error of `write`, the lines after it are dropped.
+    pub fn seal(
+        &mut self,
+        buf: &[u8],
+        mut write: impl FnMut(&[u8]) -> io::Result<Written>,
+    ) -> io::Result<()> {
+        let mut rest = buf;
+        while let Some((line, tail)) = split_line(rest) {
+            if self.partial.is_empty() {
+                self.seal_line(line, &mut write)?;
+            } else {
+                let mut full = std::mem::take(&mut self.partial);
+                full.extend_from_slice(line);
+                self.seal_line(&full, &mut write)?;
+            }
+            rest = tail;
+        }
+        self.partial.extend_from_slice(rest);
+        Ok(())
+    }
+
+    /// Seal the partial line held back, if any, as a line of its own, see
+    /// `seal`.
+    ///
+    /// # Errors
+    /// The error of `write`.
+    pub fn seal_partial(
+        &mut self,
+        mut write: impl FnMut(&[u8]) -> io::Result<Written>,
+    ) -> io::Result<()> {
+        if self.partial.is_empty() {
+            return Ok(());
+        }
+        let line = std::mem::take(&mut self.partial);
+        self.seal_line(&line, &mut write)
+    }
+
+    /// Seal `line`, after an anchor if it starts a file, and write it out.
+    fn seal_line(
+        &mut self,
+        line: &[u8],
+        write: &mut impl FnMut(&[u8]) -> io::Result<Written>,
+    ) -> io::Result<()> {
+        let (state, anchor) = (self.state, self.anchor);
+        let mut out = Vec::with_capacity(line.len().saturating_add(SEALED_TAIL_LEN));
+        if self.anchor {
+            let mut content = ANCHOR.to_vec();
+            content.extend_from_slice(hex(&self.state).as_bytes());
+            self.push_sealed(&content, &mut out);
+            self.anchor = false;
+        }
+        self.push_sealed(line, &mut out);
+
+        let written = write(&out);
+        match &written {
+            Ok(Written::Kept) => {},
+            Ok(Written::Rolled) => self.anchor = true,
+            Ok(Written::Dropped) | Err(_) => {
+                self.state = state;
+                self.anchor = anchor;
+            },
+        }
+        written.map(|_| ())
+    }
+
+    fn push_sealed(&mut self, line: &[u8], out: &mut Vec<u8>) {
+        self.state = link(self.key.as_deref(), &self.state, line);
+        out.extend_from_slice(line);
+        out.extend_from_slice(MARKER);
+        out.extend_from_slice(hex(&self.state).as_bytes());
+        out.push(b'\n');
+    }
+}
+
+/// What became of sealed lines passed to `write`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Written {
+    /// Written to the file, or held for a retry.
+    Kept,
+    /// Kept, after which the file was rolled so the next line starts a new one.
+    Rolled,
+    /// Dropped or failed over, it is not in the file.
+    Dropped,
+}
+
+/// Split `buf` after its first newline, the newline itself is dropped.
+fn split_line(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+    let i = buf.iter().position(|b| *b == b'\n')?;
+    let (line, tail) = buf.split_at(i);
+    Some((line, tail.get(1..).unwrap_or_default()))
+}
+
+/// The outcome of verifying a sequence of log files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// Every line chains to the one before it, `lines` doesn't count the
+    /// anchors.
+    Intact { lines: u64 },
+    /// The first line which doesn't match its hash, it or a line directly
+    /// before it was edited, inserted or removed.
+    Tampered { path: Utf8PathBuf, line: u64 },
+}
+
+/// Verify the hash chain of `paths`, which must be given oldest first, ie. for
+/// a rolling appender the highest numbered backup first and the active file
+/// last. `key` is the configured HMAC key, if any.
+///
+/// The chain must start at an anchor. Its hash is still checked, but when
+/// older files have been deleted by rolling the hash it continues from can't
+/// be, so only whole files can go missing from the start of the chain
+/// unnoticed.
+///
+/// # Errors
+/// - A file could not be read.
+pub fn verify<P: AsRef<Utf8Path>>(
+    paths: impl IntoIterator<Item = P>,
+    key: Option<&[u8]>,
+) -> io::Result<Verification> {
+    let mut state: Option<Hash> = None;
+    let mut lines = 0_u64;
+
+    for path in paths {
+        let path = path.as_ref();
+        let reader = BufReader::new(fs::File::open(path)?);
+        for (i, line) in (1_u64..).zip(reader.split(b'\n')) {
+            let line = line?;
+            let tampered = || {
+                Verification::Tampered {
+                    path: path.to_path_buf(),
+                    line: i,
+                }
+            };
+            let Some((content, given)) = split_sealed(&line) else {
+                return Ok(tampered());
+            };
+            let anchored = content.strip_prefix(ANCHOR).and_then(unhex);
+            let prev = match (anchored, state) {
+                (Some(from), Some(prev)) if from != prev => return Ok(tampered()),
+                (Some(from), _) => from,
+                (None, Some(prev)) => prev,
+                // leading lines were removed
+                (None, None) => return Ok(tampered()),
+            };
+            if link(key, &prev, content) != given {
+                return Ok(tampered());
+            }
+            state = Some(given);
+            if anchored.is_none() {
+                lines = lines.saturating_add(1);
+            }
+        }
+    }
+    Ok(Verification::Intact { lines })
+}
+
+fn link(key: Option<&[u8]>, prev: &Hash, line: &[u8]) -> Hash {
+    if let Some(key) = key {
+        #[allow(clippy::expect_used)] // infallible
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+        mac.update(prev);
+        mac.update(line);
+        mac.finalize().into_bytes().into()
+    } else {
+        let mut hasher = Sha256::new();
+        hasher.update(prev);
+        hasher.update(line);
+        hasher.finalize().into()
+    }
+}
+
+/// Split a sealed line, without its newline, into its content and hash.
+fn split_sealed(line: &[u8]) -> Option<(&[u8], Hash)> {
+    // at most the length, so the split can't panic
+    let at = line.len().checked_sub(SUFFIX_LEN)?;
+    let (content, suffix) = line.split_at(at);
+    let digits = suffix.strip_prefix(MARKER)?;
+    Some((content, unhex(digits)?))
+}
+
+/// The hash of the last line of the file, if it was sealed.
+fn last_hash(path: &Utf8Path) -> Option<Hash> {
+    let mut file = fs::File::open(path).ok()?;
+    let from_end = i64::try_from(SEALED_TAIL_LEN).ok()?.checked_neg()?;
+    file.seek(SeekFrom::End(from_end)).ok()?;
+    let mut tail = [0; SEALED_TAIL_LEN];
+    file.read_exact(&mut tail).ok()?;
+    let suffix = tail.strip_suffix(b"\n")?;
+    unhex(suffix.strip_prefix(MARKER)?)
+}
+
+fn hex(hash: &Hash) -> String {
+    use std::fmt::Write;
+
+    hash.iter().fold(String::with_capacity(HEX_LEN), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+fn unhex(digits: &[u8]) -> Option<Hash> {
+    if digits.len() != HEX_LEN {
+        return None;
+    }
+    let mut hash = Hash::default();
+    for (byte, pair) in hash.iter_mut().zip(digits.chunks_exact(2)) {
+        let pair = std::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(hash)
+}
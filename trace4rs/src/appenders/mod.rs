@@ -1,6 +1,9 @@
 #![allow(clippy::single_char_lifetime_names)]
 use std::{
-    collections::HashMap,
+    collections::{
+        HashMap,
+        HashSet,
+    },
     convert::TryFrom,
     fs::{
         self,
@@ -13,6 +16,7 @@ use std::{
     ops::Deref,
     path::Path,
     sync::Arc,
+    time::Duration,
 };
 
 use camino::{
@@ -27,7 +31,9 @@ use crate::{
     config::{
         self,
         AppenderId,
+        ErrorPolicy,
         Policy,
+        SyncPolicy,
     },
     env::try_expand_env_vars,
     error::{
@@ -36,9 +42,42 @@ use crate::{
     },
 };
 
+mod dedupe;
+pub(crate) use dedupe::with_event_key;
+use dedupe::Dedupe;
+
+mod durability;
+use durability::Durability;
+
+mod error_handler;
+use error_handler::ErrorHandler;
+
+mod fanout;
+pub(crate) use fanout::FanOut;
+
+mod pipeline;
+use pipeline::{
+    Pipeline,
+    Sink,
+};
+
+pub mod integrity;
+use integrity::Chain;
+
 mod rolling;
 use rolling::Rolling;
 
+mod shared;
+use shared::SharedLock;
+
+mod sifting;
+pub(crate) use sifting::{
+    fill_template,
+    template_fields,
+    with_field_values,
+};
+use sifting::Sifting;
+
 #[cfg(test)]
 mod test;
 
@@ -68,11 +107,16 @@ impl Appenders {
         }
     }
 
+    /// Correct the paths of all the appenders, returning the first error.
     pub fn correct_paths(&self) -> Result<()> {
+        let mut res = Ok(());
         for a in self {
-            a.correct_path()?;
+            let corrected = a.correct_path();
+            if res.is_ok() {
+                res = corrected;
+            }
         }
-        Ok(())
+        res
     }
 
     pub fn flush(&self) -> Result<()> {
@@ -81,6 +125,82 @@ impl Appenders {
         }
         Ok(())
     }
+
+    /// The names of the fields sifting appenders pick their files by.
+    pub fn sifted_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = self
+            .into_iter()
+            .filter_map(|a| {
+                match a {
+                    Appender::Sifting(x) => Some(x.lock().field().to_string()),
+                    _ => None,
+                }
+            })
+            .collect();
+        fields.sort();
+        fields.dedup();
+        fields
+    }
+
+    /// Whether any of the appenders collapses repeated events.
+    pub fn dedupes(&self) -> bool {
+        self.into_iter().any(|a| {
+            match a {
+                Appender::File(x) => x.lock().dedupes(),
+                Appender::RollingFile(x) => x.lock().dedupes(),
+                _ => false,
+            }
+        })
+    }
+
+    /// The number of failed writes for each appender.
+    pub fn error_counts(&self) -> HashMap<AppenderId, u64> {
+        self.appenders
+            .iter()
+            .map(|(id, a)| (id.clone(), a.error_count()))
+            .collect()
+    }
+
+    /// Resolve the targets of appenders whose error policy is to fail over.
+    ///
+    /// # Errors
+    /// - A failover target is not a known appender.
+    /// - Failing over would loop back to an appender already in the chain.
+    fn link_failovers(&self) -> Result<()> {
+        for (id, appender) in self.appenders.iter() {
+            let Some(errors) = appender.error_handler() else {
+                continue;
+            };
+            let ErrorPolicy::Failover(target) = errors.policy() else {
+                continue;
+            };
+            let Some(target_appender) = self.appenders.get(target) else {
+                return Err(Error::UnknownFailover(id.0.clone(), target.0.clone()));
+            };
+
+            // Writes hold the failing appender's lock while failing over, so any cycle
+            // would deadlock.
+            let mut seen = HashSet::from([id]);
+            let mut next = Some(target);
+            while let Some(n) = next {
+                if !seen.insert(n) {
+                    return Err(Error::FailoverCycle(id.0.clone()));
+                }
+                next = self
+                    .appenders
+                    .get(n)
+                    .and_then(Appender::error_handler)
+                    .and_then(|h| match h.policy() {
+                        ErrorPolicy::Failover(t) => Some(t.clone()),
+                        _ => None,
+                    })
+                    .and_then(|t| self.appenders.get_key_value(&t).map(|(k, _)| k));
+            }
+
+            errors.set_failover(target_appender.clone());
+        }
+        Ok(())
+    }
 }
 impl Deref for Appenders {
     type Target = AppenderMap;
@@ -97,7 +217,9 @@ impl TryFrom<&HashMap<AppenderId, config::Appender>> for Appenders {
         for (k, v) in m {
             out.insert(k.clone(), v.try_into()?);
         }
-        Ok(Self::new(out))
+        let appenders = Self::new(out);
+        appenders.link_failovers()?;
+        Ok(appenders)
     }
 }
 impl TryFrom<&config::Appender> for Appender {
@@ -107,7 +229,23 @@ impl TryFrom<&config::Appender> for Appender {
         match value {
             config::Appender::Null => Ok(crate::Appender::Null),
             config::Appender::Console { .. } => Ok(crate::Appender::new_console()),
-            config::Appender::File { path, .. } => crate::Appender::new_file(path),
+            config::Appender::File {
+                path,
+                on_error,
+                dedupe,
+                sync,
+                audit,
+                integrity,
+                shared,
+                ..
+            } => {
+                Ok(crate::Appender::new_file(path)?
+                    .with_error_policy(on_error.clone())
+                    .with_dedupe(dedupe.as_ref())
+                    .with_sync(sync.clone(), *audit)
+                    .with_integrity(integrity.as_ref())
+                    .with_shared(*shared))
+            },
             config::Appender::RollingFile {
                 path,
                 policy:
@@ -116,13 +254,47 @@ impl TryFrom<&config::Appender> for Appender {
                         maximum_file_size,
                         pattern,
                     },
+                on_error,
+                dedupe,
+                sync,
+                audit,
+                integrity,
+                shared,
                 ..
-            } => Appender::new_rolling(
+            } => {
+                Ok(Appender::new_rolling(
+                    path,
+                    pattern.as_deref(),
+                    *max_size_roll_backups as usize,
+                    maximum_file_size,
+                )?
+                .with_error_policy(on_error.clone())
+                .with_dedupe(dedupe.as_ref())
+                .with_sync(sync.clone(), *audit)
+                .with_integrity(integrity.as_ref())
+                .with_shared(*shared))
+            },
+            config::Appender::Sifting {
+                field,
                 path,
-                pattern.as_deref(),
-                *max_size_roll_backups as usize,
-                maximum_file_size,
-            ),
+                policy,
+                fallback,
+                idle_timeout_ms,
+                max_open,
+                ..
+            } => {
+                Ok(Appender::Sifting(
+                    Sifting::new(
+                        field,
+                        path,
+                        policy.as_ref(),
+                        fallback,
+                        Duration::from_millis(*idle_timeout_ms),
+                        *max_open as usize,
+                    )?
+                    .into_shared(),
+                ))
+            },
         }
     }
 }
@@ -133,9 +305,11 @@ pub enum Appender {
     /// Logs are written to stdout.
     Console(Console),
     /// A file appender.
-    File(Arc<Mutex<File>>),
+    File(Arc<Mutex<Pipeline<File>>>),
     /// A file appender which rolls files.
-    RollingFile(Arc<Mutex<Rolling>>),
+    RollingFile(Arc<Mutex<Pipeline<Rolling>>>),
+    /// A file appender per value of a field.
+    Sifting(Arc<Mutex<Sifting>>),
     /// Logs are ignored
     Null,
 }
@@ -157,7 +331,7 @@ impl Appender {
     /// # Errors
     /// - We may fail to open the file for write.
     pub fn new_file(p: impl AsRef<Utf8Path>) -> Result<Self> {
-        Ok(Self::File(Arc::new(Mutex::new(File::new(p)?))))
+        Ok(Self::File(Arc::new(Mutex::new(Pipeline::new(File::new(p)?)))))
     }
 
     /// Construct a new rolling file appender.
@@ -196,9 +370,104 @@ impl Appender {
         } else {
             Roller::new_fixed(pattern, count)
         };
-        Ok(Self::RollingFile(Arc::new(Mutex::new(Rolling::new(
-            abs_path, trigger, roller,
-        )?))))
+        Ok(Self::RollingFile(Arc::new(Mutex::new(Pipeline::new(
+            Rolling::new(abs_path, trigger, roller)?,
+        )))))
+    }
+
+    /// Set the policy applied when writing to the appender fails, it has no
+    /// effect on the console, null and sifting appenders.
+    #[must_use]
+    pub fn with_error_policy(self, policy: ErrorPolicy) -> Self {
+        let errors = Arc::new(ErrorHandler::new(policy));
+        match &self {
+            Self::Null | Self::Console(_) | Self::Sifting(_) => {},
+            Self::File(x) => x.lock().set_error_handler(errors),
+            Self::RollingFile(x) => x.lock().set_error_handler(errors),
+        }
+        self
+    }
+
+    /// Collapse consecutive identical lines written to the appender, it has no
+    /// effect on the console, null and sifting appenders.
+    #[must_use]
+    pub fn with_dedupe(self, dedupe: Option<&config::Dedupe>) -> Self {
+        let mk = || dedupe.map(|d| Dedupe::new(Duration::from_millis(d.window_ms)));
+        match &self {
+            Self::Null | Self::Console(_) | Self::Sifting(_) => {},
+            Self::File(x) => x.lock().set_dedupe(mk()),
+            Self::RollingFile(x) => x.lock().set_dedupe(mk()),
+        }
+        self
+    }
+
+    /// Set when output is fsynced to disk and whether failures are reported
+    /// by `flush_io`, see `config::SyncPolicy`. It has no effect on the
+    /// console, null and sifting appenders.
+    #[must_use]
+    pub fn with_sync(self, policy: SyncPolicy, audit: bool) -> Self {
+        let durability = Durability::new(policy, audit);
+        match &self {
+            Self::Null | Self::Console(_) | Self::Sifting(_) => {},
+            Self::File(x) => x.lock().set_durability(durability),
+            Self::RollingFile(x) => x.lock().set_durability(durability),
+        }
+        self
+    }
+
+    /// Append a running hash to every line written to the appender, see
+    /// `integrity`. It has no effect on the console, null and sifting
+    /// appenders.
+    #[must_use]
+    pub fn with_integrity(self, integrity: Option<&config::Integrity>) -> Self {
+        match &self {
+            Self::Null | Self::Console(_) | Self::Sifting(_) => {},
+            Self::File(x) => x.lock().set_integrity(integrity),
+            Self::RollingFile(x) => x.lock().set_integrity(integrity),
+        }
+        self
+    }
+
+    /// Make writes and rolls safe while other processes append to the same
+    /// file, by holding an advisory lock on `<path>.lock` around each. It has
+    /// no effect on the console, null and sifting appenders. Failing to take
+    /// the lock is a failed write, handled by the error policy.
+    #[must_use]
+    pub fn with_shared(self, shared: bool) -> Self {
+        if !shared {
+            return self;
+        }
+        match &self {
+            Self::Null | Self::Console(_) | Self::Sifting(_) => {},
+            Self::File(x) => {
+                let mut inner = x.lock();
+                let lock = Arc::new(SharedLock::new(inner.path()));
+                inner.set_shared(lock);
+            },
+            Self::RollingFile(x) => {
+                let mut inner = x.lock();
+                let lock = Arc::new(SharedLock::new(inner.path()));
+                inner.set_shared(lock);
+            },
+        }
+        self
+    }
+
+    /// The number of writes to this appender which have failed.
+    #[must_use]
+    pub fn error_count(&self) -> u64 {
+        if let Self::Sifting(x) = self {
+            return x.lock().error_count();
+        }
+        self.error_handler().map_or(0, |h| h.count())
+    }
+
+    fn error_handler(&self) -> Option<Arc<ErrorHandler>> {
+        match self {
+            Self::Null | Self::Console(_) | Self::Sifting(_) => None,
+            Self::File(x) => Some(x.lock().error_handler()),
+            Self::RollingFile(x) => Some(x.lock().error_handler()),
+        }
     }
 
     /// Correct the appender file path to what was originally opened by
@@ -221,6 +490,7 @@ impl Appender {
                     .correct_path()
                     .map_err(|e| Error::PathCorrectionFail(inner.get_path_buf(), e))
             },
+            Self::Sifting(x) => x.lock().for_each_child(Self::correct_path),
         }
     }
 
@@ -228,21 +498,28 @@ impl Appender {
     ///
     /// # Errors
     /// - An io error may occur.
+    /// - In audit mode, a write or sync has failed since the last flush.
     pub fn flush_io(&self) -> Result<()> {
         match self {
             Self::Null | Self::Console(_) => Ok(()),
             Self::File(x) => {
                 let mut inner = x.lock();
-                inner
-                    .flush()
-                    .map_err(|e| Error::FlushFail(inner.get_path_buf(), e))
+                let flushed = inner.flush();
+                // lost output matters more than a failure to flush
+                if let Some(e) = inner.take_audit_failure() {
+                    return Err(Error::AuditFail(inner.get_path_buf(), e));
+                }
+                flushed.map_err(|e| Error::FlushFail(inner.get_path_buf(), e))
             },
             Self::RollingFile(x) => {
                 let mut inner = x.lock();
-                inner
-                    .flush()
-                    .map_err(|e| Error::FlushFail(inner.get_path_buf(), e))
+                let flushed = inner.flush();
+                if let Some(e) = inner.take_audit_failure() {
+                    return Err(Error::AuditFail(inner.get_path_buf(), e));
+                }
+                flushed.map_err(|e| Error::FlushFail(inner.get_path_buf(), e))
             },
+            Self::Sifting(x) => x.lock().for_each_child(Self::flush_io),
         }
     }
 }
@@ -264,20 +541,50 @@ impl io::Write for Appender {
             Self::Console(x) => x.write(buf),
             Self::File(x) => x.deref().lock().write(buf),
             Self::RollingFile(x) => x.deref().lock().write(buf),
+            Self::Sifting(x) => x.lock().write(buf),
             Self::Null => Ok(buf.len()),
         }
     }
 
+    /// Written while holding the appender's lock throughout, so the writes of
+    /// concurrent events can't interleave.
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Console(x) => x.write_all(buf),
+            Self::File(x) => x.lock().write_all(buf),
+            Self::RollingFile(x) => x.lock().write_all(buf),
+            Self::Sifting(x) => x.lock().write_all(buf),
+            Self::Null => Ok(()),
+        }
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         match self {
             Self::Console(x) => x.flush(),
             Self::File(x) => x.lock().flush(),
             Self::RollingFile(x) => x.lock().flush(),
+            Self::Sifting(x) => x.lock().flush(),
             Self::Null => Ok(()),
         }
     }
 }
 
+/// Write all of `buf` through `write`, which may write only part of it.
+fn write_all_with(
+    mut buf: &[u8],
+    write: &mut impl FnMut(&[u8]) -> io::Result<usize>,
+) -> io::Result<()> {
+    while !buf.is_empty() {
+        match write(buf) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => buf = buf.get(n..).unwrap_or_default(),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
 /// An appender which writes to stdout.
 #[derive(Clone, Default, Debug)]
 pub struct Console;
@@ -288,7 +595,9 @@ impl Console {
 }
 impl io::Write for Console {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        io::stdout().write(buf)
+        // all of it under one lock of stdout, other threads may be printing
+        io::stdout().lock().write_all(buf)?;
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -296,7 +605,8 @@ impl io::Write for Console {
     }
 }
 
-/// An appender which writes to a file.
+/// The file written by a file appender, see `Pipeline` for what is layered
+/// over it.
 #[derive(Debug)]
 pub struct File {
     path:   Utf8PathBuf,
@@ -334,37 +644,25 @@ impl File {
         })
     }
 
-    /// Verify that the currently open file is still at the original path.
-    pub fn correct_path(&mut self) -> io::Result<()> {
-        let correct = fs::metadata(&self.path);
-        let existing = self.writer.get_ref().metadata();
-
-        if rolling::needs_remount(Some(existing), correct) {
-            self.remount()?;
-        }
-        Ok(())
-    }
-
     /// Get the target path as a str.
     pub fn path_str(&self) -> &str {
         self.path.as_str()
     }
 
-    /// Get the target path
-    pub fn get_path(&self) -> &Utf8Path {
-        &self.path
-    }
-
-    /// Get the target path buf
-    pub fn get_path_buf(&self) -> Utf8PathBuf {
-        self.path.to_path_buf()
-    }
-
     /// Remount the file at the specified path.
     /// This is useful when the file has been moved since the fd was originally
     /// mounted.
     fn remount(&mut self) -> io::Result<()> {
         self.writer.flush()?;
+        self.reopen()
+    }
+
+    /// Open a fresh writer at the specified path, recreating the parent
+    /// directory if it has been removed.
+    fn reopen(&mut self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         self.writer = Self::new_writer(&self.path)?;
         Ok(())
     }
@@ -376,12 +674,35 @@ impl File {
     }
 }
 
-impl io::Write for File {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.writer.write(buf)
+impl Sink for File {
+    fn path(&self) -> &Utf8Path {
+        &self.path
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.writer.flush()
+    fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.writer.write(buf) {
+            Ok(n) => Ok(n),
+            // The handle may have gone bad underneath us, retry once on a fresh one.
+            Err(e) => {
+                if self.reopen().is_err() {
+                    return Err(e);
+                }
+                self.writer.write(buf)
+            },
+        }
+    }
+
+    fn writer(&mut self) -> Option<&mut LineWriter<fs::File>> {
+        Some(&mut self.writer)
+    }
+
+    fn correct_path(&mut self) -> io::Result<()> {
+        let correct = fs::metadata(&self.path);
+        let existing = self.writer.get_ref().metadata();
+
+        if rolling::needs_remount(Some(existing), correct) {
+            self.remount()?;
+        }
+        Ok(())
     }
 }
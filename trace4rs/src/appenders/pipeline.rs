@@ -0,0 +1,311 @@
+use std::{
+    fs,
+    io::{
+        self,
+        LineWriter,
+        Write,
+    },
+    sync::Arc,
+};
+
+use camino::{
+    Utf8Path,
+    Utf8PathBuf,
+};
+
+use super::{
+    integrity::Written,
+    write_all_with,
+    Chain,
+    Dedupe,
+    Durability,
+    ErrorHandler,
+    SharedLock,
+};
+use crate::config;
+
+/// The file underneath a `Pipeline`, plain or rolling.
+pub trait Sink {
+    /// The configured path of the file.
+    fn path(&self) -> &Utf8Path;
+
+    /// Write `buf` to the file, retrying on a fresh handle if the current one
+    /// has gone bad.
+    fn try_write(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    /// The open writer, if there is one.
+    fn writer(&mut self) -> Option<&mut LineWriter<fs::File>>;
+
+    /// Verify that the currently open file is still at the configured path,
+    /// remounting it if not.
+    fn correct_path(&mut self) -> io::Result<()>;
+
+    /// Called after each successful write, e.g. to roll the file, returning
+    /// whether it was rolled. `shared` is set when other processes write to
+    /// the file too, and `durability` tells whether a roll must sync.
+    fn wrote(
+        &mut self,
+        _errors: &ErrorHandler,
+        _durability: &mut Durability,
+        _shared: bool,
+    ) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Catch up with what other processes did to a shared file since our
+    /// last write, such as rolling it.
+    fn catch_up(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The file the active file is rolled to, where a hash chain continues
+    /// from while the active file is empty.
+    fn backup(&self) -> Option<Utf8PathBuf> {
+        None
+    }
+}
+
+/// `Pipeline` layers what file appenders have in common over their `Sink`:
+/// dedupe, the integrity chain, the error policy, durability and, for shared
+/// files, the lock. A write goes through them in that order.
+#[derive(Debug)]
+pub struct Pipeline<S> {
+    file:       S,
+    errors:     Arc<ErrorHandler>,
+    dedupe:     Option<Dedupe>,
+    durability: Durability,
+    chain:      Option<Chain>,
+    shared:     Option<Arc<SharedLock>>,
+}
+
+impl<S: Sink> Pipeline<S> {
+    pub fn new(file: S) -> Self {
+        Self {
+            file,
+            errors: Arc::default(),
+            dedupe: None,
+            durability: Durability::default(),
+            chain: None,
+            shared: None,
+        }
+    }
+
+    /// Get the target path
+    pub fn path(&self) -> &Utf8Path {
+        self.file.path()
+    }
+
+    /// Get the target path buf
+    pub fn get_path_buf(&self) -> Utf8PathBuf {
+        self.file.path().to_path_buf()
+    }
+
+    pub fn error_handler(&self) -> Arc<ErrorHandler> {
+        Arc::clone(&self.errors)
+    }
+
+    pub fn set_error_handler(&mut self, errors: Arc<ErrorHandler>) {
+        self.errors = errors;
+    }
+
+    pub fn set_dedupe(&mut self, dedupe: Option<Dedupe>) {
+        self.dedupe = dedupe;
+    }
+
+    pub fn dedupes(&self) -> bool {
+        self.dedupe.is_some()
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    pub fn set_integrity(&mut self, integrity: Option<&config::Integrity>) {
+        self.chain = integrity.map(|i| Chain::new(i, self.file.path(), self.file.backup()));
+    }
+
+    pub fn set_shared(&mut self, lock: Arc<SharedLock>) {
+        self.shared = Some(lock);
+    }
+
+    /// The first write or sync failure since the last flush, in audit mode.
+    pub fn take_audit_failure(&mut self) -> Option<io::Error> {
+        self.durability.take_failure()
+    }
+
+    /// Verify that the currently open file is still at the original path.
+    pub fn correct_path(&mut self) -> io::Result<()> {
+        self.file.correct_path()?;
+        if let Some(chain) = &mut self.chain {
+            chain.reopened(self.file.path());
+        }
+        Ok(())
+    }
+
+    /// Write `buf` to the file, applying the error policy if that fails.
+    /// Returns the number of bytes taken and what became of them.
+    fn write_handled(&mut self, buf: &[u8]) -> io::Result<(usize, Written)> {
+        self.retry_held(false);
+        if self.errors.hold(buf) {
+            return Ok((buf.len(), Written::Kept));
+        }
+        let bs_written = match self.file.try_write(buf) {
+            Ok(n) => n,
+            // nothing reached the file, so there is nothing to sync or roll
+            Err(e) => {
+                self.durability.failed(&e);
+                return self
+                    .errors
+                    .handle(e, buf, self.file.path())
+                    .map(|n| (n, Written::Dropped));
+            },
+        };
+        if self.durability.wrote() {
+            self.sync();
+        }
+        let rolled = self
+            .file
+            .wrote(&self.errors, &mut self.durability, self.shared.is_some())?;
+        let written = if rolled {
+            Written::Rolled
+        } else {
+            Written::Kept
+        };
+        Ok((bs_written, written))
+    }
+
+    /// Write all of `buf`, which is dropped as a whole if any of it is.
+    fn write_lines(&mut self, buf: &[u8]) -> io::Result<Written> {
+        let mut written = Written::Kept;
+        write_all_with(buf, &mut |b| {
+            let (n, w) = self.write_handled(b)?;
+            if written != Written::Dropped {
+                written = w;
+            }
+            Ok(n)
+        })?;
+        Ok(written)
+    }
+
+    /// Write the output held for a retry by the error policy, see
+    /// `ErrorHandler::retry_held`.
+    fn retry_held(&mut self, now: bool) {
+        let errors = Arc::clone(&self.errors);
+        if let Err(e) = errors.retry_held(now, |b| self.file.try_write(b)) {
+            self.durability.failed(&e);
+        }
+    }
+
+    /// Fsync the file. The write has already succeeded so a failure is only
+    /// counted, and kept for the next flush in audit mode.
+    fn sync(&mut self) {
+        let Some(writer) = self.file.writer() else {
+            return;
+        };
+        if let Err(e) = self.durability.sync(writer) {
+            self.errors.record();
+            self.durability.failed(&e);
+        }
+    }
+
+    /// Seal `buf` if the appender keeps a hash chain. The whole of `buf` is
+    /// reported as written.
+    fn write_sealed(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // taken out for the duration of the write so it can borrow self
+        let Some(mut chain) = self.chain.take() else {
+            return self.write_handled(buf).map(|(n, _)| n);
+        };
+        let res = chain.seal(buf, |b| self.write_lines(b));
+        self.end_sealed(chain, res).map(|()| buf.len())
+    }
+
+    /// Put `chain` back after a write. If the write failed, say the file
+    /// couldn't be rolled, the chain continues from whatever is in the file.
+    fn end_sealed(&mut self, mut chain: Chain, res: io::Result<()>) -> io::Result<()> {
+        if res.is_err() {
+            chain.resume(self.file.path());
+        }
+        self.chain = Some(chain);
+        res
+    }
+
+    fn write_deduped(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // taken out for the duration of the write so it can borrow self
+        let Some(mut dedupe) = self.dedupe.take() else {
+            return self.write_sealed(buf);
+        };
+        let res = dedupe.write(buf, |b| self.write_sealed(b));
+        self.dedupe = Some(dedupe);
+        res
+    }
+
+    /// Apply the error policy to `buf` as a failed write, the lock of the
+    /// shared file couldn't be taken. Nothing is written to the file without
+    /// it, the policy may still retry the write or fail over.
+    fn write_unlocked(&mut self, err: io::Error, buf: &[u8]) -> io::Result<usize> {
+        self.durability.failed(&err);
+        if self.errors.hold(buf) {
+            return Ok(buf.len());
+        }
+        self.errors.handle(err, buf, self.file.path())
+    }
+
+    fn flush_inner(&mut self) -> io::Result<()> {
+        if let Some(mut dedupe) = self.dedupe.take() {
+            let res = dedupe.flush(|b| self.write_sealed(b));
+            self.dedupe = Some(dedupe);
+            res?;
+        }
+        if let Some(mut chain) = self.chain.take() {
+            let res = chain.seal_partial(|b| self.write_lines(b));
+            self.end_sealed(chain, res)?;
+        }
+        self.retry_held(true);
+        match self.file.writer() {
+            Some(w) if self.durability.is_audit() => self.durability.sync(w),
+            Some(w) => w.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: Sink> io::Write for Pipeline<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(lock) = self.shared.clone() else {
+            return self.write_deduped(buf);
+        };
+        let _guard = match lock.lock() {
+            Ok(guard) => guard,
+            Err(e) => return self.write_unlocked(e, buf),
+        };
+        if let Err(e) = self.file.catch_up() {
+            self.errors.failed(&e, self.file.path());
+        }
+        // another process may have appended since our last write
+        if let Some(chain) = &mut self.chain {
+            chain.resume(self.file.path());
+        }
+        let n = self.write_deduped(buf)?;
+        // all of it must be out before the lock is released
+        if let Some(Err(e)) = self.file.writer().map(Write::flush) {
+            self.errors.failed(&e, self.file.path());
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(lock) = self.shared.clone() else {
+            return self.flush_inner();
+        };
+        let _guard = match lock.lock() {
+            Ok(guard) => guard,
+            // what is pending waits for a flush or write which gets the lock
+            Err(e) => {
+                self.durability.failed(&e);
+                self.errors.failed(&e, self.file.path());
+                return Ok(());
+            },
+        };
+        self.flush_inner()
+    }
+}
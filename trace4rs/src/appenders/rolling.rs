@@ -13,6 +13,11 @@ use camino::{
     Utf8PathBuf,
 };
 
+use super::{
+    Durability,
+    ErrorHandler,
+    Sink,
+};
 use crate::{
     env::try_expand_env_vars,
     error::{
@@ -93,8 +98,21 @@ impl FixedWindow {
         }
     }
 
+    /// Find the last rolled file on disk, other processes may have rolled
+    /// since we last did.
+    fn rescan(&mut self) {
+        self.last = (Self::COUNT_BASE..self.count).rev().find(|i| {
+            Utf8Path::new(&self.pattern.replace(Self::INDEX_TOKEN, &i.to_string())).exists()
+        });
+    }
+
     // eas: Idk why im so dumb but this function is _bad_.
-    fn roll(&mut self, path: &Utf8Path) -> io::Result<()> {
+    fn roll(
+        &mut self,
+        path: &Utf8Path,
+        durable: bool,
+        sync_failed: &mut impl FnMut(&io::Error),
+    ) -> io::Result<()> {
         // if None, we just need to roll to zero, which happens after this block
 
         'outer: {
@@ -122,7 +140,21 @@ impl FixedWindow {
             .pattern
             .replace(Self::INDEX_TOKEN, &Self::COUNT_BASE.to_string());
 
-        fs::rename(path, new_path)
+        fs::rename(path, &new_path)?;
+        if !durable {
+            return Ok(());
+        }
+        // make the renames durable, a crash must not lose the rolled file
+        let new_dir = Utf8Path::new(&new_path).parent();
+        if let Err(e) = sync_dir(path.parent()) {
+            sync_failed(&e);
+        }
+        if new_dir != path.parent() {
+            if let Err(e) = sync_dir(new_dir) {
+                sync_failed(&e);
+            }
+        }
+        Ok(())
     }
 
     /// Roll from for example `./foo.0` to `./foo.1`
@@ -150,19 +182,48 @@ impl Roller {
         })
     }
 
-    /// Perform the roll.
+    /// Pick up the rolls done by other processes.
+    fn rescan(&mut self) {
+        if let Self::FixedWindow(x) = self {
+            x.rescan();
+        }
+    }
+
+    /// The path the active file is rolled to, if it is kept.
+    fn newest_backup(&self) -> Option<Utf8PathBuf> {
+        match self {
+            Self::Delete => None,
+            Self::FixedWindow(x) => {
+                Some(Utf8PathBuf::from(x.pattern.replace(
+                    FixedWindow::INDEX_TOKEN,
+                    &FixedWindow::COUNT_BASE.to_string(),
+                )))
+            },
+        }
+    }
+
+    /// Perform the roll. If `durable`, the file kept as a backup and the
+    /// renames are synced, sync failures are passed to `sync_failed` rather
+    /// than stopping the roll.
     pub fn roll(
         &mut self,
         path: &Utf8Path,
         writer: &mut Option<LineWriter<fs::File>>,
+        durable: bool,
+        mut sync_failed: impl FnMut(&io::Error),
     ) -> io::Result<()> {
         if let Some(w) = writer {
             w.flush()?;
+            if durable && matches!(self, Self::FixedWindow(_)) {
+                if let Err(e) = w.get_ref().sync_data() {
+                    sync_failed(&e);
+                }
+            }
         }
         writer.take();
         match self {
             Self::FixedWindow(x) => {
-                x.roll(path)?;
+                x.roll(path, durable, &mut sync_failed)?;
             },
             Self::Delete => fs::remove_file(path)?,
         }
@@ -171,8 +232,8 @@ impl Roller {
     }
 }
 
-/// An appender which writes to a file and manages rolling said file, either to
-/// backups or by deletion.
+/// A file which is rolled, either to backups or by deletion. See `Pipeline`
+/// for what is layered over it.
 #[derive(Debug)]
 pub struct Rolling {
     path:    Utf8PathBuf,
@@ -222,32 +283,11 @@ impl Rolling {
         })
     }
 
-    /// Verify that the currently open file is still at the original path.
-    pub fn correct_path(&mut self) -> io::Result<()> {
-        let correct = fs::metadata(&self.path);
-        let existing = self.writer.as_ref().map(|w| w.get_ref().metadata());
-
-        if needs_remount(existing, correct) {
-            self.remount()?;
-        }
-        Ok(())
-    }
-
     /// Get the target path as a string.
     pub fn path_str(&self) -> &str {
         self.path.as_str()
     }
 
-    /// Get the target path
-    pub fn get_path(&self) -> &Utf8Path {
-        &self.path
-    }
-
-    /// Get the target path buf
-    pub fn get_path_buf(&self) -> Utf8PathBuf {
-        self.path.to_path_buf()
-    }
-
     /// Remount the file at the specified path.
     /// This is useful when the file has been moved since the fd was originally
     /// mounted.
@@ -307,9 +347,92 @@ impl Rolling {
         Ok(LineWriter::new(f))
     }
 
-    fn maybe_roll(&mut self) -> io::Result<()> {
-        if self.trigger.should_roll(&self.meta) {
-            self.roller.roll(&self.path, &mut self.writer)?;
+    fn write_inner(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.writer {
+            Some(w) => w.write(buf),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no writer mounted")),
+        }
+    }
+
+    /// Measure the file anew, our estimate doesn't count what other processes
+    /// wrote to a shared file.
+    fn remeasure(&mut self) -> io::Result<()> {
+        if let Some(w) = &mut self.writer {
+            w.flush()?;
+            self.meta = LogFileMeta::try_from_file(w.get_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Rolling {
+    fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A previous failure abandoned the writer, try to get it back rather than
+        // dropping output forever.
+        if self.writer.is_none() {
+            self.remount()?;
+        }
+        let bs_written = match self.write_inner(buf) {
+            Ok(n) => n,
+            Err(e) => {
+                // The handle may have gone bad underneath us (file deleted along with its
+                // directory, fs remounted...), abandon it and retry once on a fresh one.
+                self.writer.take();
+                if self.remount().is_err() {
+                    return Err(e);
+                }
+                self.write_inner(buf)?
+            },
+        };
+        self.meta.wrote(bs_written);
+        Ok(bs_written)
+    }
+
+    fn writer(&mut self) -> Option<&mut LineWriter<fs::File>> {
+        self.writer.as_mut()
+    }
+
+    fn correct_path(&mut self) -> io::Result<()> {
+        let correct = fs::metadata(&self.path);
+        let existing = self.writer.as_ref().map(|w| w.get_ref().metadata());
+
+        if needs_remount(existing, correct) {
+            self.remount()?;
+        }
+        Ok(())
+    }
+
+    /// Roll the file if the trigger is met.
+    fn wrote(
+        &mut self,
+        errors: &ErrorHandler,
+        durability: &mut Durability,
+        shared: bool,
+    ) -> io::Result<bool> {
+        if shared {
+            if let Err(e) = self.remeasure() {
+                errors.failed(&e, &self.path);
+            }
+        }
+        let rolled = self.trigger.should_roll(&self.meta);
+        if rolled {
+            if shared {
+                self.roller.rescan();
+            }
+            // a sync failure is counted as for any other sync
+            self.roller.roll(
+                &self.path,
+                &mut self.writer,
+                durability.is_durable(),
+                |e| {
+                    errors.record();
+                    durability.failed(e);
+                },
+            )?;
             self.meta = self
                 .writer
                 .as_mut()
@@ -317,30 +440,35 @@ impl Rolling {
                 .transpose()?
                 .unwrap_or_default();
         }
-        Ok(())
+        Ok(rolled)
     }
-}
 
-impl io::Write for Rolling {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if let Some(w) = &mut self.writer {
-            let bs_written = w.write(buf)?;
-            self.meta.wrote(bs_written);
-            self.maybe_roll()?;
-            Ok(bs_written)
-        } else {
-            Ok(0)
-        }
+    /// Another process may have rolled the file.
+    fn catch_up(&mut self) -> io::Result<()> {
+        self.correct_path()
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.writer
-            .as_mut()
-            .map(std::io::Write::flush)
-            .transpose()?;
-        Ok(())
+    fn backup(&self) -> Option<Utf8PathBuf> {
+        self.roller.newest_backup()
     }
 }
+
+/// Fsync `dir` so renames within it survive a crash.
+#[cfg(unix)]
+fn sync_dir(dir: Option<&Utf8Path>) -> io::Result<()> {
+    let dir = dir
+        .filter(|d| !d.as_str().is_empty())
+        .unwrap_or_else(|| Utf8Path::new("."));
+    fs::File::open(dir)?.sync_all()
+}
+
+/// Directories can't be opened for sync outside of unix.
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn sync_dir(_dir: Option<&Utf8Path>) -> io::Result<()> {
+    Ok(())
+}
+
 pub(crate) fn needs_remount(
     existing: Option<io::Result<fs::Metadata>>,
     correct: io::Result<fs::Metadata>,
@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    io,
+};
+
+use camino::{
+    Utf8Path,
+    Utf8PathBuf,
+};
+use fs2::FileExt;
+use once_cell::sync::OnceCell;
+
+/// `SharedLock` is an advisory lock (`flock` on unix) held around every write
+/// and roll of a file which other processes append to as well, so that lines
+/// don't interleave and only one process rolls the file.
+///
+/// The lock is taken on `<path>.lock` rather than the log file itself since
+/// rolling renames the log file out from under the other processes. The lock
+/// file is opened on first use, so that failing to open it is a failed write
+/// like any other.
+#[derive(Debug)]
+pub struct SharedLock {
+    path: Utf8PathBuf,
+    file: OnceCell<fs::File>,
+}
+impl SharedLock {
+    pub fn new(path: &Utf8Path) -> Self {
+        Self {
+            path: Utf8PathBuf::from(format!("{path}.lock")),
+            file: OnceCell::new(),
+        }
+    }
+
+    /// Take the lock, blocking until it is acquired. It is held until the
+    /// guard is dropped.
+    pub fn lock(&self) -> io::Result<SharedGuard<'_>> {
+        let file = self.file.get_or_try_init(|| {
+            fs::File::options()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&self.path)
+        })?;
+        file.lock_exclusive()?;
+        Ok(SharedGuard(file))
+    }
+}
+
+/// Releases the `SharedLock` when dropped.
+#[derive(Debug)]
+pub struct SharedGuard<'l>(&'l fs::File);
+impl Drop for SharedGuard<'_> {
+    fn drop(&mut self) {
+        // not `self.0.unlock()`, newer toolchains have an inherent `File::unlock`.
+        // Nothing can be done if it fails, the lock goes with the file at worst.
+        let _ = FileExt::unlock(self.0);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{
+        self,
+        Write,
+    },
+    sync::{
+        mpsc,
+        Arc,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use parking_lot::Mutex;
+
+use super::Appender;
+use crate::{
+    config::Policy,
+    error::{
+        Error,
+        Result,
+    },
+};
+
+const FIELD_TOKEN_START: &str = "$FIELD{";
+/// Idle children are looked for at most this often.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+thread_local! {
+    /// The values of the sifted fields for the event being written on this
+    /// thread.
+    static CURRENT: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Make `values` visible to the sifting appenders written to by `f`.
+pub fn with_field_values<R>(values: Vec<(String, String)>, f: impl FnOnce() -> R) -> R {
+    let prev = CURRENT.with(|c| c.replace(values));
+    let res = f();
+    CURRENT.with(|c| c.replace(prev));
+    res
+}
+
+fn field_value(name: &str) -> Option<String> {
+    CURRENT.with(|c| {
+        c.borrow()
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+    })
+}
+
+/// An appender which writes to a file per value of a field, see
+/// `config::Appender::Sifting`.
+#[derive(Debug)]
+pub struct Sifting {
+    field:         String,
+    token:         String,
+    path:          String,
+    policy:        Option<Policy>,
+    fallback:      String,
+    idle:          Duration,
+    max_open:      usize,
+    children:      HashMap<String, Child>,
+    last_sweep:    Instant,
+    /// The failed writes of the files closed so far, and of the files which
+    /// couldn't be opened.
+    closed_errors: u64,
+    /// Disconnecting the channel wakes and stops the sweeper thread.
+    _stop:         Option<mpsc::Sender<()>>,
+}
+
+#[derive(Debug)]
+struct Child {
+    appender:  Appender,
+    last_used: Instant,
+}
+
+impl Sifting {
+    /// Create a new sifting appender, no file is created until it is written
+    /// to.
+    ///
+    /// # Errors
+    /// - `path` doesn't contain `$FIELD{<field>}`.
+    /// - The size limit of `policy` is invalid.
+    pub fn new(
+        field: &str,
+        path: &str,
+        policy: Option<&Policy>,
+        fallback: &str,
+        idle: Duration,
+        max_open: usize,
+    ) -> Result<Self> {
+        let token = format!("{FIELD_TOKEN_START}{field}}}");
+        if !path.contains(&token) {
+            return Err(Error::SiftingPath {
+                path:  path.to_string(),
+                field: field.to_string(),
+            });
+        }
+        if let Some(p) = policy {
+            Policy::calculate_maximum_file_size(&p.maximum_file_size)?;
+        }
+        Ok(Self {
+            field: field.to_string(),
+            token,
+            path: path.to_string(),
+            policy: policy.cloned(),
+            fallback: fallback.to_string(),
+            idle,
+            max_open: max_open.max(1),
+            children: HashMap::new(),
+            last_sweep: Instant::now(),
+            closed_errors: 0,
+            _stop: None,
+        })
+    }
+
+    /// Share the appender, closing its idle files every `SWEEP_INTERVAL` on a
+    /// background thread so that they are closed even if nothing more is
+    /// written. The thread stops when the appender is dropped.
+    pub fn into_shared(self) -> Arc<Mutex<Self>> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let this = Arc::new(Mutex::new(Self {
+            _stop: Some(stop),
+            ..self
+        }));
+        let weak = Arc::downgrade(&this);
+        // without the thread idle files are still closed by writes
+        let _ = thread::Builder::new()
+            .name("trace4rs-sifting".to_string())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(SWEEP_INTERVAL)
+                {
+                    let Some(sifting) = weak.upgrade() else {
+                        return;
+                    };
+                    sifting.lock().close_idle(Instant::now());
+                }
+            });
+        this
+    }
+
+    /// The name of the field whose value picks the file.
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Apply `f` to every open file, returning the first error.
+    pub fn for_each_child(&self, mut f: impl FnMut(&Appender) -> Result<()>) -> Result<()> {
+        let mut res = Ok(());
+        for child in self.children.values() {
+            let applied = f(&child.appender);
+            if res.is_ok() {
+                res = applied;
+            }
+        }
+        res
+    }
+
+    /// The number of writes which have failed, to the files open now or
+    /// closed before.
+    pub fn error_count(&self) -> u64 {
+        self.children.values().fold(self.closed_errors, |count, c| {
+            count.saturating_add(c.appender.error_count())
+        })
+    }
+
+    fn path_for(&self, value: &str) -> String {
+        self.path.replace(&self.token, value)
+    }
+
+    fn child(&mut self, value: &str) -> io::Result<&mut Appender> {
+        let now = Instant::now();
+        if now.duration_since(self.last_sweep) >= SWEEP_INTERVAL {
+            self.close_idle(now);
+        }
+        if !self.children.contains_key(value) {
+            if self.children.len() >= self.max_open {
+                self.close_least_recent();
+            }
+            let appender = match self.open(value) {
+                Ok(appender) => appender,
+                Err(e) => {
+                    self.closed_errors = self.closed_errors.saturating_add(1);
+                    return Err(e);
+                },
+            };
+            self.children.insert(value.to_string(), Child {
+                appender,
+                last_used: now,
+            });
+        }
+        let child = self
+            .children
+            .get_mut(value)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        child.last_used = now;
+        Ok(&mut child.appender)
+    }
+
+    fn open(&self, value: &str) -> io::Result<Appender> {
+        let path = self.path_for(value);
+        let res = match &self.policy {
+            None => Appender::new_file(&path),
+            Some(p) => Appender::new_rolling(
+                &path,
+                p.pattern
+                    .as_deref()
+                    .map(|pat| pat.replace(&self.token, value))
+                    .as_deref(),
+                p.max_size_roll_backups as usize,
+                &p.maximum_file_size,
+            ),
+        };
+        res.map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    fn close_idle(&mut self, now: Instant) {
+        let idle = self.idle;
+        let mut closed_errors = self.closed_errors;
+        self.children.retain(|_, c| {
+            let keep = now.duration_since(c.last_used) < idle;
+            if !keep {
+                closed_errors = closed_errors.saturating_add(c.close());
+            }
+            keep
+        });
+        self.closed_errors = closed_errors;
+        self.last_sweep = now;
+    }
+
+    fn close_least_recent(&mut self) {
+        let lru = self
+            .children
+            .iter()
+            .min_by_key(|(_, c)| c.last_used)
+            .map(|(v, _)| v.clone());
+        if let Some(mut child) = lru.and_then(|v| self.children.remove(&v)) {
+            self.closed_errors = self.closed_errors.saturating_add(child.close());
+        }
+    }
+}
+
+impl Child {
+    /// Flush the file before it is dropped, returning the number of its
+    /// writes which failed.
+    fn close(&mut self) -> u64 {
+        let _ = self.appender.flush();
+        self.appender.error_count()
+    }
+}
+
+impl io::Write for Sifting {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let value =
+            field_value(&self.field).map_or_else(|| self.fallback.clone(), |v| sanitize(&v));
+        self.child(&value)?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut res = Ok(());
+        for child in self.children.values_mut() {
+            let flushed = child.appender.flush();
+            if res.is_ok() {
+                res = flushed;
+            }
+        }
+        res
+    }
+}
+
+/// The names of the fields referenced by `$FIELD{<name>}` in `template`.
+pub fn template_fields(template: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some((_, tail)) = rest.split_once(FIELD_TOKEN_START) {
+        let Some((name, tail)) = tail.split_once('}') else {
+            break;
+        };
+        names.push(name.to_string());
+        rest = tail;
+    }
+    names
+}
+
+/// Replace each `$FIELD{<name>}` in `template` by the value of `<name>` made
+/// safe for a file name, or by `fallback` if there is no such value.
+pub fn fill_template(template: &str, values: &[(String, String)], fallback: &str) -> String {
+    template_fields(template)
+        .iter()
+        .fold(template.to_string(), |path, name| {
+            let value = values
+                .iter()
+                .find(|(n, _)| n == name)
+                .map_or_else(|| fallback.to_string(), |(_, v)| sanitize(v));
+            path.replace(&format!("{FIELD_TOKEN_START}{name}}}"), &value)
+        })
+}
+
+/// Make a field value safe to use as part of a file name, keeping distinct
+/// values distinct. Bytes other than ASCII alphanumerics, `-`, `_` and `.` are
+/// percent-encoded, as is a leading `.` so that the name can't be `.` or `..`.
+/// An empty value is a lone `%`, which no other value encodes to.
+fn sanitize(value: &str) -> String {
+    use std::fmt::Write;
+
+    if value.is_empty() {
+        return "%".to_string();
+    }
+    let safe = |i: usize, b: u8| {
+        b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || (b == b'.' && i > 0)
+    };
+    value
+        .bytes()
+        .enumerate()
+        .fold(String::with_capacity(value.len()), |mut s, (i, b)| {
+            if safe(i, b) {
+                s.push(char::from(b));
+            } else {
+                let _ = write!(s, "%{b:02X}");
+            }
+            s
+        })
+}
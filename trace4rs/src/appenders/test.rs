@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     fs,
     io::Write,
     path::Component,
@@ -19,8 +20,18 @@ use super::rolling::{
     Trigger,
 };
 use crate::{
-    appenders::rolling::FixedWindow,
+    appenders::{
+        rolling::FixedWindow,
+        Appenders,
+        Pipeline,
+    },
+    config::{
+        self,
+        AppenderId,
+        ErrorPolicy,
+    },
     Appender,
+    Error,
 };
 
 fn get_appender(path: &Utf8Path, pattern: &Option<String>) -> Appender {
@@ -144,9 +155,9 @@ fn correct_paths() {
     let path = tmpdir.path().join("logfile");
     let trigger = Trigger::Size { limit: 10 };
     let roller = Roller::Delete;
-    let mut appender = Appender::RollingFile(Arc::new(Mutex::new(
+    let mut appender = Appender::RollingFile(Arc::new(Mutex::new(Pipeline::new(
         Rolling::new(path.to_str().unwrap(), trigger, roller).unwrap(),
-    )));
+    ))));
 
     // sanity check/add some bytes to the file
     let buf1 = "123456789".to_string();
@@ -173,6 +184,38 @@ fn correct_paths() {
     assert_eq!(content, buf2);
 }
 
+#[test]
+fn correct_paths_past_failure() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap();
+    let broken_dir = path.join("broken");
+    fs::create_dir(&broken_dir).unwrap();
+
+    let mut appenders = HashMap::new();
+    appenders.insert(
+        AppenderId::from("broken"),
+        Appender::new_file(broken_dir.join("file.log")).unwrap(),
+    );
+    // several, so that some come after the broken one whatever the order
+    let moved: Vec<Utf8PathBuf> = (0..4).map(|i| path.join(format!("{i}.log"))).collect();
+    for p in &moved {
+        appenders.insert(AppenderId::from(p.as_str()), Appender::new_file(p).unwrap());
+    }
+    let appenders = Appenders::new(appenders);
+
+    // a file where the directory was, it can't be recreated
+    fs::remove_dir_all(&broken_dir).unwrap();
+    fs::write(&broken_dir, "").unwrap();
+    for p in &moved {
+        fs::rename(p, format!("{p}.moved")).unwrap();
+    }
+
+    assert!(appenders.correct_paths().is_err());
+    for p in &moved {
+        assert!(p.exists(), "{p} should have been remounted");
+    }
+}
+
 #[test]
 fn size_delete_roll() {
     let tmpdir = tempfile::tempdir().unwrap();
@@ -180,9 +223,9 @@ fn size_delete_roll() {
     let trigger = Trigger::Size { limit: 10 };
     let roller = Roller::Delete;
 
-    let mut appender = Appender::RollingFile(Arc::new(Mutex::new(
+    let mut appender = Appender::RollingFile(Arc::new(Mutex::new(Pipeline::new(
         Rolling::new(path.to_str().unwrap(), trigger, roller).unwrap(),
-    )));
+    ))));
     let buf1 = "123456789".to_string();
     appender.write_all(buf1.as_bytes()).unwrap();
     appender.flush_io().unwrap();
@@ -239,3 +282,597 @@ fn size_window_roll_no_pattern_relative() {
     let appender = get_appender(&rel_path, &None);
     window_roll(&path, pattern, appender);
 }
+
+fn failing_over(path: impl Into<String>, to: &str) -> config::Appender {
+    let mut appender = config::Appender::file(path);
+    if let config::Appender::File { on_error, .. } = &mut appender {
+        *on_error = ErrorPolicy::Failover(to.into());
+    }
+    appender
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn failover() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("failover.log");
+
+    let conf: HashMap<AppenderId, config::Appender> = literally::hmap! {
+        // writes to /dev/full always fail with ENOSPC
+        "full" => failing_over("/dev/full", "backup"),
+        "backup" => config::Appender::file(path.as_str()),
+    };
+    let appenders = Appenders::try_from(&conf).unwrap();
+
+    let mut full = appenders.get(&"full".into()).unwrap().clone();
+    full.write_all(b"hello\n").unwrap();
+    appenders.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+    let counts = appenders.error_counts();
+    assert_eq!(counts.get(&"full".into()), Some(&1));
+    assert_eq!(counts.get(&"backup".into()), Some(&0));
+}
+
+#[test]
+fn failover_cycle() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap();
+
+    let cyclic: HashMap<AppenderId, config::Appender> = literally::hmap! {
+        "a" => failing_over(path.join("a.log"), "b"),
+        "b" => failing_over(path.join("b.log"), "a"),
+    };
+    let res = Appenders::try_from(&cyclic);
+    assert!(matches!(res, Err(Error::FailoverCycle(_))));
+
+    let unknown: HashMap<AppenderId, config::Appender> = literally::hmap! {
+        "a" => failing_over(path.join("a.log"), "nope"),
+    };
+    assert!(matches!(
+        Appenders::try_from(&unknown),
+        Err(Error::UnknownFailover(..))
+    ));
+}
+
+#[test]
+fn retry_held() {
+    use std::io;
+
+    use super::error_handler::ErrorHandler;
+
+    let path = Utf8Path::new("retry.log");
+    let full = |_: &[u8]| -> io::Result<usize> { Err(io::Error::other("full")) };
+    let errors = ErrorHandler::new(ErrorPolicy::Retry {
+        attempts:   2,
+        backoff_ms: 60_000,
+    });
+
+    // the failed write is held rather than waited on, and what follows queues
+    // behind it
+    assert_eq!(errors.handle(full(b"").unwrap_err(), b"a\n", path).unwrap(), 2);
+    assert!(errors.hold(b"b\n"));
+    // not due yet
+    errors.retry_held(false, full).unwrap();
+    errors.retry_held(true, full).unwrap();
+    let mut out = Vec::new();
+    errors.retry_held(true, |b| out.write(b)).unwrap();
+    assert_eq!(out, b"a\nb\n");
+    assert!(!errors.hold(b"c\n"));
+
+    // dropped once the attempts run out, and counted with what queued behind
+    errors.handle(full(b"").unwrap_err(), b"d\n", path).unwrap();
+    assert!(errors.hold(b"e\n"));
+    errors.retry_held(true, full).unwrap();
+    assert!(errors.retry_held(true, full).is_err());
+    assert!(!errors.hold(b"f\n"));
+    assert_eq!(errors.count(), 3);
+
+    // what would go over the cap is dropped and counted
+    errors.handle(full(b"").unwrap_err(), b"g\n", path).unwrap();
+    let big = vec![b'x'; 600 * 1024];
+    assert!(errors.hold(&big));
+    assert!(errors.hold(&big));
+    assert_eq!(errors.count(), 5);
+    out.clear();
+    errors.retry_held(true, |b| out.write(b)).unwrap();
+    assert_eq!(out.len(), 2 + big.len());
+}
+
+#[test]
+fn dedupe() {
+    use super::with_event_key;
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("dedupe.log");
+
+    let mut appender = Appender::new_file(&path)
+        .unwrap()
+        .with_dedupe(Some(&config::Dedupe { window_ms: 60_000 }));
+
+    appender.write_all(b"a\n").unwrap();
+    appender.write_all(b"a\n").unwrap();
+    appender.write_all(b"a\n").unwrap();
+    // lines written for an event are compared on its key
+    with_event_key(Some(b"b".to_vec()), || {
+        appender.write_all(b"00:00:03 b\n").unwrap();
+        appender.write_all(b"00:00:04 b\n").unwrap();
+    });
+    appender.flush_io().unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "a\nlast message repeated 2 times\n00:00:03 b\nlast message repeated 1 times\n"
+    );
+
+    // flushing again must not repeat the summary
+    appender.flush_io().unwrap();
+    appender.write_all(b"c\n").unwrap();
+    appender.flush_io().unwrap();
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .ends_with("repeated 1 times\nc\n"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn audit() {
+    use crate::config::SyncPolicy;
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("audit.log");
+
+    let mut synced = Appender::new_file(&path)
+        .unwrap()
+        .with_sync(SyncPolicy::EveryEvent, true);
+    synced.write_all(b"hello\n").unwrap();
+    synced.flush_io().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+
+    // writes to /dev/full always fail with ENOSPC
+    let mut full = Appender::new_file("/dev/full")
+        .unwrap()
+        .with_sync(SyncPolicy::Never, true);
+    // dropped by the default `Ignore` policy, the audit still reports it
+    full.write_all(b"hello\n").unwrap();
+    assert!(matches!(full.flush_io(), Err(Error::AuditFail(..))));
+}
+
+#[test]
+fn durable_rolls() {
+    use super::durability::Durability;
+    use crate::config::SyncPolicy;
+
+    // rolls only sync for appenders which sync at all
+    assert!(!Durability::new(SyncPolicy::Never, false).is_durable());
+    assert!(Durability::new(SyncPolicy::Never, true).is_durable());
+    assert!(Durability::new(SyncPolicy::EveryEvents(10), false).is_durable());
+}
+
+#[test]
+fn integrity() {
+    use crate::appenders::integrity::{
+        verify,
+        Verification,
+    };
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("chain.log");
+    let key = config::Integrity {
+        key: Some("secret".to_string()),
+    };
+
+    let mut appender = Appender::new_rolling(path.as_str(), None, 5, "400 B")
+        .unwrap()
+        .with_integrity(Some(&key));
+    for i in 0..10 {
+        writeln!(appender, "line {i}").unwrap();
+    }
+    appender.flush_io().unwrap();
+
+    // oldest first, the active file last
+    let mut files: Vec<Utf8PathBuf> = (0..5)
+        .rev()
+        .map(|i| Utf8PathBuf::from(format!("{path}.{i}")))
+        .filter(|p| p.exists())
+        .collect();
+    files.push(path.clone());
+    assert!(files.len() > 2, "the chain should span rolls");
+
+    let secret = Some(&b"secret"[..]);
+    assert_eq!(
+        verify(&files, secret).unwrap(),
+        Verification::Intact { lines: 10 }
+    );
+    assert!(matches!(
+        verify(&files, Some(&b"wrong"[..])).unwrap(),
+        Verification::Tampered { line: 1, .. }
+    ));
+
+    // a new appender picks the chain back up
+    let mut reopened = Appender::new_rolling(path.as_str(), None, 5, "400 B")
+        .unwrap()
+        .with_integrity(Some(&key));
+    writeln!(reopened, "line 10").unwrap();
+    reopened.flush_io().unwrap();
+    assert_eq!(
+        verify(&files, secret).unwrap(),
+        Verification::Intact { lines: 11 }
+    );
+
+    let tampered = files.get(1).unwrap();
+    let content = fs::read_to_string(tampered).unwrap();
+    fs::write(tampered, content.replacen("line", "lime", 1)).unwrap();
+    assert_eq!(
+        verify(&files, secret).unwrap(),
+        Verification::Tampered {
+            path: tampered.clone(),
+            // after the anchor
+            line: 2,
+        }
+    );
+}
+
+#[test]
+fn integrity_start() {
+    use crate::appenders::integrity::{
+        verify,
+        Verification,
+    };
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("chain.log");
+    let key = config::Integrity {
+        key: Some("secret".to_string()),
+    };
+
+    let mut appender = Appender::new_file(&path)
+        .unwrap()
+        .with_integrity(Some(&key));
+    for i in 0..3 {
+        writeln!(appender, "line {i}").unwrap();
+    }
+    appender.flush_io().unwrap();
+
+    let secret = Some(&b"secret"[..]);
+    assert_eq!(
+        verify([&path], secret).unwrap(),
+        Verification::Intact { lines: 3 }
+    );
+
+    let content = fs::read_to_string(&path).unwrap();
+    let verify_as = |changed: String| {
+        fs::write(&path, changed).unwrap();
+        verify([&path], secret).unwrap()
+    };
+    let tampered = |line| {
+        Verification::Tampered {
+            path: path.clone(),
+            line,
+        }
+    };
+    assert_eq!(
+        verify_as(content.replacen("#chain-anchor:0", "#chain-anchor:1", 1)),
+        tampered(1)
+    );
+    assert_eq!(verify_as(content.replacen("line 0", "lime 0", 1)), tampered(2));
+    // the chain must start at an anchor
+    let dropped = |n| content.split_inclusive('\n').skip(n).collect::<String>();
+    assert_eq!(verify_as(dropped(1)), tampered(1));
+    assert_eq!(verify_as(dropped(2)), tampered(1));
+}
+
+#[cfg(unix)]
+#[test]
+fn integrity_dropped_write() {
+    use std::os::unix::fs::symlink;
+
+    use crate::appenders::integrity::{
+        verify,
+        Verification,
+    };
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let dir = Utf8Path::from_path(tmpdir.path()).unwrap();
+    let target = dir.join("target.log");
+    let link = dir.join("chain.log");
+    symlink(&target, &link).unwrap();
+
+    let mut appender = Appender::new_file(&link)
+        .unwrap()
+        .with_integrity(Some(&config::Integrity::default()));
+    let point_to = |appender: &Appender, to: &Utf8Path| {
+        fs::remove_file(&link).unwrap();
+        symlink(to, &link).unwrap();
+        appender.correct_path().unwrap();
+    };
+    writeln!(appender, "kept").unwrap();
+    // writes to /dev/full always fail with ENOSPC, the line is dropped by the
+    // default `Ignore` policy
+    point_to(&appender, Utf8Path::new("/dev/full"));
+    writeln!(appender, "dropped").unwrap();
+    point_to(&appender, &target);
+    writeln!(appender, "kept too").unwrap();
+    appender.flush_io().unwrap();
+
+    assert_eq!(
+        verify([&target], None).unwrap(),
+        Verification::Intact { lines: 2 }
+    );
+}
+
+#[test]
+fn integrity_after_roll() {
+    use crate::appenders::integrity::{
+        verify,
+        Verification,
+    };
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("chain.log");
+    let backup = Utf8PathBuf::from(format!("{path}.0"));
+    let key = config::Integrity::default();
+    let open = |size| {
+        Appender::new_rolling(path.as_str(), None, 5, size)
+            .unwrap()
+            .with_integrity(Some(&key))
+    };
+
+    let mut rolled = open("20 B");
+    writeln!(rolled, "rolled right away").unwrap();
+    rolled.flush_io().unwrap();
+    drop(rolled);
+    assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+    // a restart continues the chain from the backup
+    let mut restarted = open("1 KB");
+    writeln!(restarted, "line 1").unwrap();
+    restarted.flush_io().unwrap();
+    assert_eq!(
+        verify([&backup, &path], None).unwrap(),
+        Verification::Intact { lines: 2 }
+    );
+}
+
+#[test]
+fn sifting_max_open() {
+    use std::time::Duration;
+
+    use super::{
+        sifting::Sifting,
+        with_field_values,
+    };
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap();
+    let template = path.join("$FIELD{id}.log");
+
+    assert!(matches!(
+        Sifting::new("other", template.as_str(), None, "x", Duration::MAX, 1),
+        Err(Error::SiftingPath { .. })
+    ));
+
+    let sifting = Sifting::new("id", template.as_str(), None, "x", Duration::MAX, 2).unwrap();
+    let mut appender = Appender::Sifting(Arc::new(Mutex::new(sifting)));
+    for id in ["a", "b", "c", "a"] {
+        with_field_values(vec![("id".to_string(), id.to_string())], || {
+            appender.write_all(format!("{id}\n").as_bytes()).unwrap();
+        });
+    }
+    appender.flush_io().unwrap();
+
+    let Appender::Sifting(inner) = &appender else {
+        unreachable!()
+    };
+    let mut open = 0;
+    inner
+        .lock()
+        .for_each_child(|_| {
+            open += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(open, 2);
+    assert_eq!(fs::read_to_string(path.join("a.log")).unwrap(), "a\na\n");
+    assert_eq!(fs::read_to_string(path.join("c.log")).unwrap(), "c\n");
+}
+
+#[test]
+fn sifting_open_failure() {
+    use std::time::Duration;
+
+    use super::{
+        sifting::Sifting,
+        with_field_values,
+    };
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap();
+    // a file where the directory should be, unwritable even as root
+    fs::write(path.join("not_a_dir"), "").unwrap();
+    let template = path.join("not_a_dir/$FIELD{id}.log");
+
+    let sifting = Sifting::new("id", template.as_str(), None, "x", Duration::MAX, 2).unwrap();
+    let mut appender = Appender::Sifting(Arc::new(Mutex::new(sifting)));
+    with_field_values(vec![("id".to_string(), "a".to_string())], || {
+        assert!(appender.write_all(b"a\n").is_err());
+    });
+    assert_eq!(appender.error_count(), 1);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sifting_idle() {
+    use std::{
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
+    };
+
+    use super::{
+        sifting::Sifting,
+        with_field_values,
+    };
+
+    // writes to /dev/full always fail with ENOSPC
+    let sifting = Sifting::new("id", "/dev/$FIELD{id}", None, "x", Duration::ZERO, 2)
+        .unwrap()
+        .into_shared();
+    let mut appender = Appender::Sifting(Arc::clone(&sifting));
+    with_field_values(vec![("id".to_string(), "full".to_string())], || {
+        appender.write_all(b"dropped\n").unwrap();
+    });
+    assert_eq!(appender.error_count(), 1);
+
+    // closed with nothing more written
+    let open = || {
+        let mut count = 0;
+        sifting
+            .lock()
+            .for_each_child(|_| {
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+        count
+    };
+    let started = Instant::now();
+    while open() > 0 && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(open(), 0);
+    assert_eq!(appender.error_count(), 1, "error counts never go down");
+}
+
+#[test]
+fn shared_rolling() {
+    use std::{
+        collections::BTreeSet,
+        thread,
+    };
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("shared.log");
+
+    // separate appenders on the same file stand in for separate processes
+    let writers: Vec<_> = (0..2)
+        .map(|w| {
+            let mut appender = Appender::new_rolling(path.as_str(), None, 50, "200 B")
+                .unwrap()
+                .with_shared(true);
+            thread::spawn(move || {
+                for i in 0..100 {
+                    appender
+                        .write_all(format!("writer {w} line {i:03}\n").as_bytes())
+                        .unwrap();
+                }
+                appender.flush_io().unwrap();
+            })
+        })
+        .collect();
+    for w in writers {
+        w.join().unwrap();
+    }
+
+    let mut lines = BTreeSet::new();
+    let mut count = 0;
+    for entry in fs::read_dir(tmpdir.path()).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_name().to_string_lossy().ends_with(".lock") {
+            continue;
+        }
+        let content = fs::read_to_string(entry.path()).unwrap();
+        // rolled on the real size, not a per process estimate
+        assert!(content.len() <= 200 + "writer 0 line 000\n".len());
+        for line in content.lines() {
+            lines.insert(line.to_string());
+            count += 1;
+        }
+    }
+    // nothing was lost to a clobbering roll, nor interleaved
+    assert_eq!(count, 200);
+    assert_eq!(lines.len(), 200);
+}
+
+#[test]
+fn shared_lock_failure() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(tmpdir.path()).unwrap().join("shared.log");
+    let lock_path = format!("{path}.lock");
+    // the lock file can't be opened with a directory in its place
+    fs::create_dir_all(&lock_path).unwrap();
+
+    let mut appender = Appender::new_file(&path)
+        .unwrap()
+        .with_error_policy(ErrorPolicy::Retry {
+            attempts:   3,
+            backoff_ms: 0,
+        })
+        .with_shared(true);
+    appender.write_all(b"hello\n").unwrap();
+    appender.flush_io().unwrap();
+
+    // nothing is written unlocked, the write is held for a retry instead
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    assert_eq!(appender.error_count(), 2);
+
+    fs::remove_dir(&lock_path).unwrap();
+    appender.write_all(b"world\n").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello\nworld\n");
+}
+
+#[test]
+fn fanout_lines_never_tear() {
+    use std::thread;
+
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::FanOut;
+
+    const THREADS: usize = 8;
+    const EVENTS: usize = 200;
+    // longer than the buffer of a `LineWriter`
+    const LEN: usize = 3000;
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let dir = Utf8Path::from_path(tmpdir.path()).unwrap();
+    let paths = [dir.join("a.log"), dir.join("b.log")];
+    let fanout = FanOut::new(
+        paths
+            .iter()
+            .map(|p| Appender::new_file(p).unwrap())
+            .collect(),
+    );
+
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let fanout = &fanout;
+            s.spawn(move || {
+                let c = char::from(b'a' + u8::try_from(t).unwrap());
+                let line = c.to_string().repeat(LEN);
+                for _ in 0..EVENTS {
+                    let mut w = fanout.make_writer();
+                    // formatting may take many small writes
+                    for chunk in line.as_bytes().chunks(7) {
+                        w.write_all(chunk).unwrap();
+                    }
+                    w.write_all(b"\n").unwrap();
+                }
+            });
+        }
+    });
+
+    for path in &paths {
+        let content = fs::read_to_string(path).unwrap();
+        let mut lines = 0;
+        for line in content.lines() {
+            let first = line.chars().next().unwrap();
+            assert_eq!(line.len(), LEN);
+            assert!(line.chars().all(|c| c == first), "torn line in {path}");
+            lines += 1;
+        }
+        assert_eq!(lines, THREADS * EVENTS);
+    }
+}
@@ -0,0 +1,58 @@
+//! Verify the hash chain of log files written with `integrity` configured.
+//!
+//! ```text
+//! trace4rs-verify [--key-env VAR] FILE...
+//! ```
+//!
+//! Files must be given oldest first. With `--key-env` the HMAC key is read
+//! from the environment variable `VAR`. Exits with `1` if a tampered line is
+//! found and `2` on any other error.
+#![allow(clippy::print_stdout, clippy::print_stderr)] // it's a cli
+
+use std::{
+    env,
+    process::ExitCode,
+};
+
+use trace4rs::Verification;
+
+const USAGE: &str = "usage: trace4rs-verify [--key-env VAR] FILE...";
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).peekable();
+    let key = if args.next_if(|a| a == "--key-env").is_some() {
+        let Some(var) = args.next() else {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        };
+        match env::var(&var) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                eprintln!("trace4rs-verify: {var}: {e}");
+                return ExitCode::from(2);
+            },
+        }
+    } else {
+        None
+    };
+    let paths: Vec<String> = args.collect();
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    }
+
+    match trace4rs::verify(&paths, key.as_deref().map(str::as_bytes)) {
+        Ok(Verification::Intact { lines }) => {
+            println!("ok: {lines} lines verified");
+            ExitCode::SUCCESS
+        },
+        Ok(Verification::Tampered { path, line }) => {
+            println!("tampered: {path}:{line}");
+            ExitCode::from(1)
+        },
+        Err(e) => {
+            eprintln!("trace4rs-verify: {e}");
+            ExitCode::from(2)
+        },
+    }
+}
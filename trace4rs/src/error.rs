@@ -14,6 +14,9 @@ pub enum Error {
     #[error("Failed to flush appender for '{0}': {1}")]
     FlushFail(Utf8PathBuf, #[source] io::Error),
 
+    #[error("Audited output to '{0}' was lost: {1}")]
+    AuditFail(Utf8PathBuf, #[source] io::Error),
+
     #[error("error setting the global default logger: {0}")]
     SetGlobalDefaultError(#[from] tracing::subscriber::SetGlobalDefaultError),
 
@@ -37,6 +40,27 @@ pub enum Error {
     #[error("Failed to absolutize input path")]
     AbsolutizeFailed(#[from] io::Error),
 
+    #[error("Appender '{0}' fails over to unknown appender '{1}'")]
+    UnknownFailover(String, String),
+
+    #[error("Appender '{0}' fails over in a cycle")]
+    FailoverCycle(String),
+
+    #[error("Sifting appender path '{path}' doesn't contain '$FIELD{{{field}}}'")]
+    SiftingPath { path: String, field: String },
+
+    #[error("Can't register field '{0}': {1}")]
+    RegisterField(String, String),
+
+    #[error("Invalid redaction pattern '{0}': {1}")]
+    RedactionPattern(String, #[source] regex::Error),
+
+    #[error("A redaction needs a field or a pattern")]
+    EmptyRedaction,
+
+    #[error("A redaction replacing values with a hash needs a key")]
+    RedactionKey,
+
     #[error("Error in the config: {0}")]
     Config(#[from] trace4rs_config::error::Error),
 }
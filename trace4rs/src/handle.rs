@@ -1,4 +1,12 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 
 use tracing::Subscriber;
 use tracing_subscriber::{
@@ -14,7 +22,11 @@ use tracing_subscriber::{
 };
 
 use crate::{
-    config::Config,
+    config::{
+        AppenderId,
+        Config,
+        Target,
+    },
     error::Result,
     subscriber::layer::T4Layer,
 };
@@ -137,6 +149,40 @@ where
         Ok(())
     }
 
+    /// The number of failed writes for each appender, see
+    /// `config::ErrorPolicy` for how failures are handled.
+    ///
+    /// # Errors
+    /// - We were unable to access the subscriber.
+    pub fn error_counts(&self) -> Result<HashMap<AppenderId, u64>> {
+        Ok(self
+            .reload_handle
+            .with_current(|ls| ls.appenders().error_counts())?)
+    }
+
+    /// The number of events dropped by sampling for each logger configured
+    /// with `sample`, the default logger is keyed by `None`.
+    ///
+    /// # Errors
+    /// - We were unable to access the subscriber.
+    pub fn sampled_out_counts(&self) -> Result<HashMap<Option<Target>, u64>> {
+        Ok(self
+            .reload_handle
+            .with_current(T4Layer::sampled_out_counts)?)
+    }
+
+    /// Set the context field `name`, written with every event, to `value`.
+    /// It replaces the value from the config, if any, and is kept when the
+    /// config is updated.
+    ///
+    /// # Errors
+    /// - We were unable to access the subscriber.
+    pub fn set_context(&self, name: &str, value: &str) -> Result<()> {
+        Ok(self
+            .reload_handle
+            .with_current(|ls| ls.context().set(name, value))?)
+    }
+
     /// Correct the output path of log files if they have been moved.
     ///
     /// # Errors
@@ -148,6 +194,33 @@ where
         Ok(())
     }
 
+    /// Periodically check that the files which are the target of appenders
+    /// are still at their configured paths, remounting them when they have
+    /// been moved or deleted by an external tool (logrotate, an operator...).
+    ///
+    /// The check runs on a background thread until the returned
+    /// `PathWatcher` is dropped.
+    #[must_use]
+    pub fn watch_appender_paths(&self, interval: Duration) -> PathWatcher
+    where
+        Reg: 'static,
+    {
+        let handle = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // Failures are retried on the next tick, the appenders keep
+                // their current handle in the meantime.
+                let _ = handle.correct_appender_paths();
+            }
+        });
+
+        PathWatcher {
+            stop:   Some(stop),
+            thread: Some(thread),
+        }
+    }
+
     /// Update with the given config.
     ///
     /// # Errors
@@ -156,6 +229,16 @@ where
     /// opening a file for write.
     pub fn update(&mut self, config: &Config) -> Result<()> {
         let ls = T4Layer::from_config(config)?;
+        let (set, dispatch) = self.reload_handle.with_current(|current| {
+            (current.context().set_values(), current.summaries().dispatch())
+        })?;
+        for (name, value) in set {
+            ls.context().set(&name, &value);
+        }
+        // the new layer isn't registered with the subscriber itself
+        if let Some(dispatch) = dispatch {
+            ls.summaries().start(dispatch);
+        }
         Ok(self.reload_handle.reload(ls)?)
     }
 }
@@ -167,3 +250,22 @@ impl<Reg> Clone for Handle<Reg> {
         }
     }
 }
+
+/// A guard for the background appender path checker started by
+/// `Handle::watch_appender_paths`, the checker is stopped when this is
+/// dropped.
+#[derive(Debug)]
+pub struct PathWatcher {
+    stop:   Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for PathWatcher {
+    fn drop(&mut self) {
+        // disconnecting the channel wakes the checker
+        self.stop.take();
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
@@ -22,12 +22,22 @@ pub mod handle;
 #[cfg(test)]
 mod test;
 
-pub use appenders::Appender;
+pub use appenders::{
+    integrity::{
+        verify,
+        Verification,
+    },
+    Appender,
+};
 pub use error::{
     Error,
     Result,
 };
 pub use handle::Handle;
+pub use subscriber::{
+    offset::set_utc_offset_refresh,
+    user_fields::register_field,
+};
 pub use trace4rs_config::{
     config,
     config::Config,
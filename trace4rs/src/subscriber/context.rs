@@ -0,0 +1,74 @@
+//! Fields written with every event, from the config's `context` or set at
+//! runtime with `Handle::set_context`.
+use std::{
+    collections::HashMap,
+    fmt,
+};
+
+use parking_lot::RwLock;
+use tracing_subscriber::fmt::format::Writer;
+
+use crate::env::expand_env_vars;
+
+#[derive(Debug, Default)]
+pub struct ContextFields {
+    /// The values by name, in the order they are written.
+    values: RwLock<Vec<(String, String)>>,
+    /// The values set at runtime, kept when the config is reloaded.
+    set:    RwLock<Vec<(String, String)>>,
+}
+impl ContextFields {
+    /// The context of the config, with environment variables expanded and
+    /// ordered by name.
+    pub fn new(config: &HashMap<String, String>) -> Self {
+        let mut values: Vec<_> = config
+            .iter()
+            .map(|(name, value)| (name.clone(), expand_env_vars(value).into_owned()))
+            .collect();
+        values.sort();
+        Self {
+            values: RwLock::new(values),
+            set:    RwLock::default(),
+        }
+    }
+
+    /// Set the value of `name`, replacing the config's if it has one.
+    pub fn set(&self, name: &str, value: &str) {
+        insert(&mut self.values.write(), name, value);
+        insert(&mut self.set.write(), name, value);
+    }
+
+    /// The values set at runtime.
+    pub fn set_values(&self) -> Vec<(String, String)> {
+        self.set.read().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.read().is_empty()
+    }
+
+    /// Write the value of `name`, returning whether there is one.
+    pub fn write_value(&self, mut writer: Writer<'_>, name: &str) -> Result<bool, fmt::Error> {
+        match self.values.read().iter().find(|(n, _)| n == name) {
+            Some((_, value)) => writer.write_str(value).map(|()| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Write every field as ` name="value"`, as the normal format writes the
+    /// fields of events.
+    pub fn write_fields(&self, mut writer: Writer<'_>) -> fmt::Result {
+        for (name, value) in self.values.read().iter() {
+            #[allow(clippy::use_debug)] // quoted and escaped like event fields
+            write!(writer, " {name}={value:?}")?;
+        }
+        Ok(())
+    }
+}
+
+fn insert(values: &mut Vec<(String, String)>, name: &str, value: &str) {
+    match values.iter_mut().find(|(n, _)| n == name) {
+        Some((_, v)) => value.clone_into(v),
+        None => values.push((name.to_string(), value.to_string())),
+    }
+}
@@ -0,0 +1,40 @@
+//! The key appenders collapsing repeated events compare events on, see
+//! `appenders::with_event_key`.
+use std::{
+    fmt,
+    io::Write,
+};
+
+use tracing::{
+    field::{
+        Field,
+        Visit,
+    },
+    Event,
+};
+
+/// Separates the parts of a key.
+const SEP: char = '\0';
+
+/// The callsite, level and field values of `event`, message included. Two
+/// events with equal keys format to the same line but for the time and the
+/// spans they happened in.
+pub fn event_key(event: &Event<'_>) -> Vec<u8> {
+    let meta = event.metadata();
+    let mut key = KeyVisitor(Vec::new());
+    let _ = write!(key.0, "{}{SEP}{}{SEP}{}", meta.target(), meta.name(), meta.level());
+    event.record(&mut key);
+    key.0
+}
+
+struct KeyVisitor(Vec<u8>);
+impl Visit for KeyVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        #[allow(clippy::use_debug)] // the value's only representation
+        let _ = write!(self.0, "{SEP}{}={value:?}", field.name());
+    }
+}
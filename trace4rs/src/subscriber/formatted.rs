@@ -0,0 +1,126 @@
+//! Formatting each event once per format, however many loggers share it.
+use std::{
+    cell::{
+        Cell,
+        RefCell,
+    },
+    io,
+    mem,
+};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::config::{
+    Format,
+    Sanitize,
+    Timezone,
+};
+
+thread_local! {
+    /// Where `Capture` puts the event being formatted on this thread.
+    static CAPTURED: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    /// The buffers of the previous event, reused for the next.
+    static POOL: Cell<Vec<(Option<usize>, Vec<u8>)>> = const { Cell::new(Vec::new()) };
+}
+
+/// Numbers the distinct formats, time zones, timestamp formats and
+/// sanitizations of a set of loggers, loggers with the same number share their
+/// formatted output.
+#[derive(Default)]
+pub struct FormatIds(Vec<(Format, Timezone, Option<String>, Option<Sanitize>)>);
+impl FormatIds {
+    pub fn id(
+        &mut self,
+        format: &Format,
+        timezone: Timezone,
+        timestamp: Option<&str>,
+        sanitize: Option<Sanitize>,
+    ) -> usize {
+        if let Some(i) = self.0.iter().position(|(f, tz, ts, s)| {
+            f == format && *tz == timezone && ts.as_deref() == timestamp && *s == sanitize
+        }) {
+            return i;
+        }
+        self.0.push((
+            format.clone(),
+            timezone,
+            timestamp.map(str::to_string),
+            sanitize,
+        ));
+        self.0.len().saturating_sub(1)
+    }
+}
+
+/// The output of formatting one event, by format id.
+pub struct Formatted {
+    /// The first `used` entries hold this event, the rest are spare buffers.
+    entries: Vec<(Option<usize>, Vec<u8>)>,
+    used:    usize,
+}
+impl Formatted {
+    /// Start an event, reusing the thread's buffers unless an event is
+    /// already being written on this thread.
+    pub fn new() -> Self {
+        Self {
+            entries: POOL.with(Cell::take),
+            used:    0,
+        }
+    }
+
+    /// The event formatted with format `id`, `format` is only called if no
+    /// logger with that format has formatted it yet. Without an id the event
+    /// is always formatted.
+    pub fn get_or_format(&mut self, id: Option<usize>, format: impl FnOnce()) -> &[u8] {
+        let cached = id.and_then(|id| {
+            self.entries
+                .iter()
+                .take(self.used)
+                .position(|(i, _)| *i == Some(id))
+        });
+        let slot = cached.unwrap_or_else(|| {
+            let slot = self.used;
+            if slot == self.entries.len() {
+                self.entries.push((None, Vec::new()));
+            }
+            self.used = self.used.saturating_add(1);
+            if let Some((slot_id, buf)) = self.entries.get_mut(slot) {
+                *slot_id = id;
+                buf.clear();
+                format();
+                CAPTURED.with(|c| mem::swap(&mut *c.borrow_mut(), buf));
+            }
+            slot
+        });
+        self.entries
+            .get(slot)
+            .map(|(_, buf)| buf.as_slice())
+            .unwrap_or_default()
+    }
+}
+impl Drop for Formatted {
+    fn drop(&mut self) {
+        let entries = mem::take(&mut self.entries);
+        POOL.with(|p| p.set(entries));
+    }
+}
+
+/// A `MakeWriter` capturing what is written to it for `Formatted`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Capture;
+impl<'a> MakeWriter<'a> for Capture {
+    type Writer = Capture;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Capture
+    }
+}
+impl io::Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        CAPTURED.with(|c| c.borrow_mut().extend_from_slice(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,10 +1,29 @@
 use std::{
     borrow::Cow,
-    fmt,
+    fmt::{
+        self,
+        Write as _,
+    },
+    sync::Arc,
+    thread,
 };
 
 use once_cell::sync::Lazy;
-use trace4rs_fmtorp::FieldValueWriter;
+
+use time::{
+    error::InvalidFormatDescription,
+    format_description::{
+        self,
+        well_known::Rfc3339,
+        OwnedFormatItem,
+    },
+    OffsetDateTime,
+    UtcOffset,
+};
+use trace4rs_fmtorp::{
+    Field,
+    FieldValueWriter,
+};
 use tracing::{
     field::Visit,
     Event,
@@ -24,50 +43,153 @@ use tracing_subscriber::{
         FormatEvent,
         FormatFields,
     },
-    registry::LookupSpan,
+    registry::{
+        LookupSpan,
+        SpanRef,
+    },
 };
 
-use crate::config::Format as ConfigFormat;
-
-const TIME_FORMAT: time::format_description::well_known::Rfc3339 =
-    time::format_description::well_known::Rfc3339;
-
-static NORMAL_FMT: Lazy<Format<Full, UtcOffsetTime>> =
-    Lazy::new(|| Format::default().with_timer(UtcOffsetTime).with_ansi(false));
+use super::{
+    context::ContextFields,
+    log4rs_pattern,
+    offset::local_offset,
+    redact::Redactor,
+    sanitize::Sanitizing,
+    span_fields::SpanFields,
+    user_fields::{
+        self,
+        UserField,
+    },
+};
+use crate::config::{
+    Format as ConfigFormat,
+    Sanitize,
+    Timezone,
+};
 
 #[derive(Debug)]
 pub enum EventFormatter {
-    Normal,
-    MessageOnly,
-    Custom(FmtorpFormatter),
+    /// The normal format followed by the context fields.
+    Normal(Format<Full, Timestamp>, Arc<ContextFields>, Option<Sanitize>),
+    MessageOnly(Arc<Redactor>, Option<Sanitize>),
+    Custom(Box<FmtorpFormatter>),
 }
 
 impl Default for EventFormatter {
     fn default() -> Self {
-        Self::Normal
+        Self::normal(Timestamp::default(), Arc::default(), None)
     }
 }
 
 impl From<ConfigFormat> for EventFormatter {
     fn from(f: ConfigFormat) -> Self {
-        match f {
-            ConfigFormat::Normal => Self::Normal,
-            ConfigFormat::MessageOnly => Self::MessageOnly,
+        Self::new(
+            f,
+            Timezone::default(),
+            None,
+            Arc::default(),
+            Arc::default(),
+            None,
+        )
+    }
+}
+
+impl EventFormatter {
+    /// A formatter for `format` writing timestamps in `timezone` and, unless
+    /// a field has a format of its own, in the `timestamp` format. The normal
+    /// format writes the `context` fields, custom formats as `{ctx.<name>}`.
+    /// Fields formatted by the `FmtContext`, as the normal format and `{f}`
+    /// do, are redacted by the logger's `RedactedFields`, others by `redactor`.
+    /// Messages and field values are sanitized as `sanitize` says.
+    pub fn new(
+        format: ConfigFormat,
+        timezone: Timezone,
+        timestamp: Option<&str>,
+        context: Arc<ContextFields>,
+        redactor: Arc<Redactor>,
+        sanitize: Option<Sanitize>,
+    ) -> Self {
+        let timestamp_format = match timestamp.map(TimestampFormat::parse).transpose() {
+            Ok(t) => t.unwrap_or_default(),
+            #[allow(clippy::print_stderr)] // necessary error surfacing
+            Err(e) => {
+                eprintln!("trace4rs: Error parsing logger timestamp format: {e}, using rfc3339");
+                TimestampFormat::default()
+            },
+        };
+        match format {
+            ConfigFormat::Normal => {
+                let timer = Timestamp {
+                    timezone,
+                    format: timestamp_format,
+                };
+                Self::normal(timer, context, sanitize)
+            },
+            ConfigFormat::MessageOnly => Self::MessageOnly(redactor, sanitize),
             ConfigFormat::Custom(s) => {
-                match FmtorpFormatter::new(s) {
-                    Ok(c) => Self::Custom(c),
-                    #[allow(clippy::print_stderr)] // necessary error surfacing
-                    Err(e) => {
-                        eprintln!(
-                            "trace4rs: Error parsing logger custom format: {e}, using default \
-                             formatter"
-                        );
-                        Self::default()
-                    },
-                }
+                let custom = FmtorpFormatter::new(
+                    s,
+                    timezone,
+                    &timestamp_format,
+                    &context,
+                    &redactor,
+                    sanitize,
+                );
+                let timer = Timestamp {
+                    timezone,
+                    format: timestamp_format,
+                };
+                Self::custom(custom, "custom format", timer, context, sanitize)
+            },
+            ConfigFormat::Log4rsPattern(p) => {
+                let custom = log4rs_pattern::translate(&p).and_then(|s| {
+                    FmtorpFormatter::new(
+                        s,
+                        timezone,
+                        &timestamp_format,
+                        &context,
+                        &redactor,
+                        sanitize,
+                    )
+                });
+                let timer = Timestamp {
+                    timezone,
+                    format: timestamp_format,
+                };
+                Self::custom(custom, "log4rs pattern", timer, context, sanitize)
+            },
+        }
+    }
+
+    /// The custom formatter, or the normal one if it failed to parse.
+    fn custom(
+        custom: Result<FmtorpFormatter, trace4rs_fmtorp::Error>,
+        what: &str,
+        timestamp: Timestamp,
+        context: Arc<ContextFields>,
+        sanitize: Option<Sanitize>,
+    ) -> Self {
+        match custom {
+            Ok(c) => Self::Custom(Box::new(c)),
+            #[allow(clippy::print_stderr)] // necessary error surfacing
+            Err(e) => {
+                eprintln!("trace4rs: Error parsing logger {what}, using default formatter: {e}");
+                Self::normal(timestamp, context, sanitize)
             },
         }
     }
+
+    fn normal(
+        timestamp: Timestamp,
+        context: Arc<ContextFields>,
+        sanitize: Option<Sanitize>,
+    ) -> Self {
+        Self::Normal(
+            Format::default().with_timer(timestamp).with_ansi(false),
+            context,
+            sanitize,
+        )
+    }
 }
 
 impl<Reg, N> FormatEvent<Reg, N> for EventFormatter
@@ -78,17 +200,33 @@ where
     fn format_event(
         &self,
         ctx: &FmtContext<'_, Reg, N>,
-        writer: Writer<'_>,
+        mut writer: Writer<'_>,
         event: &Event<'_>,
     ) -> std::fmt::Result {
         match self {
             Self::Custom(fmtr) => fmtr.format_event(ctx, writer, event),
-            Self::MessageOnly => {
-                let mut vs = SingleFieldVisitor::new(true, writer, MESSAGE_FIELD_NAME);
-                event.record(&mut vs);
+            Self::MessageOnly(redactor, sanitize) => {
+                let mut vs = SingleFieldVisitor::new(true, writer, MESSAGE_FIELD_NAME)
+                    .sanitized(*sanitize);
+                redactor.record(event, &mut vs);
                 Ok(())
             },
-            Self::Normal => NORMAL_FMT.format_event(ctx, writer, event),
+            Self::Normal(fmt, context, None) if context.is_empty() => {
+                fmt.format_event(ctx, writer, event)
+            },
+            // only the fields of the line come from the event, the rest is
+            // unchanged by sanitizing
+            Self::Normal(fmt, context, sanitize) => {
+                let mut line = String::new();
+                fmt.format_event(ctx, Writer::new(&mut line), event)?;
+                let text = line.strip_suffix('\n').unwrap_or(&line);
+                match sanitize {
+                    Some(mode) => Sanitizing::new(writer.by_ref(), *mode).write_str(text)?,
+                    None => writer.write_str(text)?,
+                }
+                context.write_fields(writer.by_ref())?;
+                writeln!(writer)
+            },
         }
     }
 }
@@ -100,7 +238,20 @@ mod fields {
     pub const TARGET: &str = "t";
     pub const MESSAGE: &str = "m";
     pub const FIELDS: &str = "f";
+    pub const FIELD: &str = "f.*";
+    pub const CONTEXT: &str = "ctx.*";
     pub const LEVEL: &str = "l";
+    pub const FILE: &str = "file";
+    pub const LINE: &str = "line";
+    pub const MODULE: &str = "module";
+    pub const THREAD: &str = "thread";
+    pub const THREAD_ID: &str = "thread_id";
+    pub const PID: &str = "pid";
+    pub const HOSTNAME: &str = "hostname";
+    pub const NEWLINE: &str = "n";
+    pub const SPAN: &str = "s";
+    pub const SPAN_PATH: &str = "S";
+    pub const SPAN_FIELDS: &str = "S.fields";
     pub static FIELD_SET: once_cell::sync::Lazy<HashSet<&'static str>> =
         once_cell::sync::Lazy::new(|| {
             let mut set = HashSet::new();
@@ -109,69 +260,306 @@ mod fields {
             set.insert(TARGET);
             set.insert(MESSAGE);
             set.insert(FIELDS);
+            set.insert(FIELD);
+            set.insert(CONTEXT);
             set.insert(LEVEL);
+            set.insert(FILE);
+            set.insert(LINE);
+            set.insert(MODULE);
+            set.insert(THREAD);
+            set.insert(THREAD_ID);
+            set.insert(PID);
+            set.insert(HOSTNAME);
+            set.insert(NEWLINE);
+            set.insert(SPAN);
+            set.insert(SPAN_PATH);
+            set.insert(SPAN_FIELDS);
             set
         });
+
+    /// The built-in fields and those registered by the application.
+    pub fn known() -> HashSet<&'static str> {
+        let mut known = FIELD_SET.clone();
+        known.extend(super::user_fields::names());
+        known
+    }
 }
 
-struct CustomValueWriter<'ctx, 'evt, Reg, N> {
-    ctx:   &'ctx FmtContext<'ctx, Reg, N>,
-    event: &'evt Event<'evt>,
+/// Information about the process, looked up once.
+struct Process {
+    pid:      u32,
+    hostname: String,
 }
-impl<'ctx, 'evt, Broker, N> CustomValueWriter<'ctx, 'evt, Broker, N> {
-    fn format_timestamp(mut writer: format::Writer<'_>) -> fmt::Result {
-        use tracing_subscriber::fmt::time::OffsetTime;
 
-        let (o, _) = utc_offset::get_utc_offset();
-        let t = OffsetTime::new(o, TIME_FORMAT);
-        t.format_time(&mut writer)
+static PROCESS: Lazy<Process> = Lazy::new(|| {
+    Process {
+        pid:      std::process::id(),
+        hostname: hostname::get()
+            .map(|h| h.to_string_lossy().into_owned())
+            .unwrap_or_default(),
     }
+});
 
-    fn format_timestamp_utc(mut writer: format::Writer<'_>) -> fmt::Result {
-        let t = tracing_subscriber::fmt::time::UtcTime::rfc_3339();
-        t.format_time(&mut writer)
-    }
+/// Information about a thread, looked up once per thread.
+struct Thread {
+    name: String,
+    /// The number of the thread's `ThreadId`.
+    id:   String,
+}
+
+thread_local! {
+    static THREAD: Thread = {
+        let current = thread::current();
+        let id = format!("{:?}", current.id());
+        Thread {
+            name: current.name().unwrap_or("<unnamed>").to_string(),
+            id:   id
+                .trim_start_matches("ThreadId(")
+                .trim_end_matches(')')
+                .to_string(),
+        }
+    };
+}
+
+struct CustomValueWriter<'ctx, 'evt, Reg, N> {
+    ctx:        &'ctx FmtContext<'ctx, Reg, N>,
+    event:      &'evt Event<'evt>,
+    /// The timestamp of each field, by field index.
+    timestamps: &'ctx [Option<Timestamp>],
+    /// The fields placed by `{f.<name>}`, which `{f:rest}` leaves out.
+    placed:     &'ctx [String],
+    /// The writer of each registered field, by field index.
+    registered: &'ctx [Option<UserField>],
+    context:    &'ctx ContextFields,
+    redactor:   &'ctx Redactor,
+    sanitize:   Option<Sanitize>,
 }
 impl<'ctx, 'evt, Reg, N> FieldValueWriter for CustomValueWriter<'ctx, 'evt, Reg, N>
 where
     Reg: Subscriber + for<'a> LookupSpan<'a>,
     N: for<'a> FormatFields<'a> + 'static,
 {
-    fn write_value(&self, mut writer: format::Writer<'_>, field: &'static str) -> fmt::Result {
+    fn write_value(&self, writer: format::Writer<'_>, field: Field<'_>) -> fmt::Result {
+        match self.sanitize {
+            Some(mode) if field.name != fields::NEWLINE => {
+                self.write_field(Writer::new(&mut Sanitizing::new(writer, mode)), field)
+            },
+            _ => self.write_field(writer, field),
+        }
+    }
+}
+impl<'ctx, 'evt, Reg, N> CustomValueWriter<'ctx, 'evt, Reg, N>
+where
+    Reg: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn write_field(&self, mut writer: format::Writer<'_>, field: Field<'_>) -> fmt::Result {
         let normalized_meta = self.event.normalized_metadata();
         let meta = normalized_meta
             .as_ref()
             .unwrap_or_else(|| self.event.metadata());
 
-        if field == fields::TIMESTAMP {
-            Self::format_timestamp(writer)?;
-        } else if field == fields::TIMESTAMP_UTC {
-            Self::format_timestamp_utc(writer)?;
-        } else if field == fields::TARGET {
+        let Field { index, name, .. } = field;
+        if name == fields::TIMESTAMP || name == fields::TIMESTAMP_UTC {
+            if let Some(timestamp) = self.timestamps.get(index).and_then(Option::as_ref) {
+                timestamp.format_time(&mut writer)?;
+            }
+        } else if name == fields::TARGET {
             write!(writer, "{}", meta.target())?;
-        } else if field == fields::MESSAGE {
+        } else if name == fields::MESSAGE {
             let mut vs = SingleFieldVisitor::new(false, writer.by_ref(), MESSAGE_FIELD_NAME);
-            self.event.record(&mut vs);
-        } else if field == fields::FIELDS {
-            self.ctx.format_fields(writer, self.event)?;
-        } else if field == fields::LEVEL {
+            self.redactor.record(self.event, &mut vs);
+        } else if name == fields::FIELDS {
+            if field.arg == Some(FIELDS_REST) {
+                let mut vs = RestVisitor::new(self.placed, writer);
+                self.redactor.record(self.event, &mut vs);
+                vs.result?;
+            } else {
+                self.ctx.format_fields(writer, self.event)?;
+            }
+        } else if name == fields::FIELD {
+            let param = field.param.unwrap_or_default();
+            if !self.write_named(writer.by_ref(), param)? {
+                writer.write_str(default_value(field))?;
+            }
+        } else if name == fields::CONTEXT {
+            let param = field.param.unwrap_or_default();
+            if !self.context.write_value(writer.by_ref(), param)? {
+                writer.write_str(default_value(field))?;
+            }
+        } else if name == fields::LEVEL {
             write!(writer, "{}", meta.level())?;
+        } else if name == fields::FILE {
+            writer.write_str(meta.file().unwrap_or_default())?;
+        } else if name == fields::LINE {
+            if let Some(line) = meta.line() {
+                write!(writer, "{line}")?;
+            }
+        } else if name == fields::MODULE {
+            writer.write_str(meta.module_path().unwrap_or_default())?;
+        } else if name == fields::THREAD {
+            THREAD.with(|t| writer.write_str(&t.name))?;
+        } else if name == fields::THREAD_ID {
+            THREAD.with(|t| writer.write_str(&t.id))?;
+        } else if name == fields::PID {
+            write!(writer, "{}", PROCESS.pid)?;
+        } else if name == fields::HOSTNAME {
+            writer.write_str(&PROCESS.hostname)?;
+        } else if name == fields::NEWLINE {
+            writeln!(writer)?;
+        } else if name == fields::SPAN {
+            if let Some(span) = self.ctx.event_scope().and_then(|mut s| s.next()) {
+                writer.write_str(span.name())?;
+            }
+        } else if name == fields::SPAN_PATH {
+            self.write_spans(writer, ":", |span, w| w.write_str(span.name()))?;
+        } else if name == fields::SPAN_FIELDS {
+            self.write_spans(writer, " ", |span, w| {
+                match span.extensions().get::<SpanFields>() {
+                    Some(fields) => w.write_str(&fields.formatted),
+                    None => Ok(()),
+                }
+            })?;
+        } else if let Some(registered) = self.registered.get(index).and_then(Option::as_ref) {
+            registered.write(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write each span the event is within, root first, skipping those for
+    /// which `write_span` writes nothing.
+    fn write_spans(
+        &self,
+        mut writer: format::Writer<'_>,
+        separator: &str,
+        write_span: impl Fn(&SpanRef<'_, Reg>, &mut String) -> fmt::Result,
+    ) -> fmt::Result {
+        let Some(scope) = self.ctx.event_scope() else {
+            return Ok(());
+        };
+        let mut first = true;
+        let mut buf = String::new();
+        for span in scope.from_root() {
+            buf.clear();
+            write_span(&span, &mut buf)?;
+            if buf.is_empty() {
+                continue;
+            }
+            if !first {
+                writer.write_str(separator)?;
+            }
+            writer.write_str(&buf)?;
+            first = false;
         }
         Ok(())
     }
+
+    /// Write the value of the field `name` of the event or else of the
+    /// closest span which has it, returning whether there was one.
+    fn write_named(&self, mut writer: format::Writer<'_>, name: &str) -> Result<bool, fmt::Error> {
+        let mut vs = NamedFieldVisitor::new(name, writer.by_ref());
+        self.redactor.record(self.event, &mut vs);
+        if vs.found {
+            return vs.result.map(|()| true);
+        }
+        for span in self.ctx.event_scope().into_iter().flatten() {
+            let extensions = span.extensions();
+            if let Some(value) = extensions.get::<SpanFields>().and_then(|f| f.get(name)) {
+                writer.write_str(value)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
 }
 
-/// EAS: Follow strat from `NORMAL_FMT`
+/// The argument of `{f}` leaving out the fields placed by `{f.<name>}`.
+const FIELDS_REST: &str = "rest";
+/// Starts the argument of `{f.<name>}` or `{ctx.<name>}`, the rest of which is
+/// written when the field has no value.
+const DEFAULT: &str = "default=";
+
+/// The value written by `{f.<name>:default=<value>}` or
+/// `{ctx.<name>:default=<value>}` when the field has no value.
+fn default_value(field: Field<'_>) -> &str {
+    field
+        .arg
+        .and_then(|a| a.strip_prefix(DEFAULT))
+        .unwrap_or_default()
+}
+
+/// EAS: Follow strat from `EventFormatter::Normal`
 #[derive(Debug)]
 pub struct FmtorpFormatter {
-    fmtr: trace4rs_fmtorp::Fmtr<'static>,
+    fmtr:       trace4rs_fmtorp::Fmtr<'static>,
+    /// The timestamp of each field, by field index, parsed up front.
+    timestamps: Vec<Option<Timestamp>>,
+    /// The fields placed by `{f.<name>}`.
+    placed:     Vec<String>,
+    /// The writer of each registered field, by field index, looked up once.
+    registered: Vec<Option<UserField>>,
+    context:    Arc<ContextFields>,
+    redactor:   Arc<Redactor>,
+    sanitize:   Option<Sanitize>,
 }
 
 impl FmtorpFormatter {
-    fn new(fmt_str: impl Into<Cow<'static, str>>) -> Result<Self, trace4rs_fmtorp::Error> {
-        let fmtr = trace4rs_fmtorp::Fmtr::new(fmt_str, &fields::FIELD_SET)?;
+    fn new(
+        fmt_str: impl Into<Cow<'static, str>>,
+        timezone: Timezone,
+        timestamp: &TimestampFormat,
+        context: &Arc<ContextFields>,
+        redactor: &Arc<Redactor>,
+        sanitize: Option<Sanitize>,
+    ) -> Result<Self, trace4rs_fmtorp::Error> {
+        let fmtr = trace4rs_fmtorp::Fmtr::new(fmt_str, &fields::known())?;
+        let timestamps = fmtr
+            .fields()
+            .map(|field| {
+                let timezone = match field.name {
+                    fields::TIMESTAMP => timezone,
+                    fields::TIMESTAMP_UTC => Timezone::Utc,
+                    fields::FIELDS if field.arg.is_some_and(|a| a != FIELDS_REST) => {
+                        return Err(invalid_argument(&fmtr, field, "expected 'rest'"));
+                    },
+                    fields::FIELD | fields::CONTEXT
+                        if field.arg.is_some_and(|a| !a.starts_with(DEFAULT)) =>
+                    {
+                        return Err(invalid_argument(&fmtr, field, "expected 'default=<value>'"));
+                    },
+                    fields::FIELD | fields::FIELDS | fields::CONTEXT => return Ok(None),
+                    _ if field.arg.is_some() => {
+                        return Err(invalid_argument(&fmtr, field, "the field takes no argument"));
+                    },
+                    _ => return Ok(None),
+                };
+                let format = match field.arg {
+                    Some(arg) => {
+                        TimestampFormat::parse(arg)
+                            .map_err(|e| invalid_argument(&fmtr, field, &e.to_string()))?
+                    },
+                    None => timestamp.clone(),
+                };
+                Ok(Some(Timestamp { timezone, format }))
+            })
+            .collect::<Result<_, _>>()?;
+        let placed = fmtr
+            .fields()
+            .filter(|f| f.name == fields::FIELD)
+            .filter_map(|f| f.param)
+            .map(str::to_string)
+            .collect();
+        let registered = fmtr.fields().map(|f| user_fields::get(f.name)).collect();
 
-        Ok(Self { fmtr })
+        Ok(Self {
+            fmtr,
+            timestamps,
+            placed,
+            registered,
+            context: Arc::clone(context),
+            redactor: Arc::clone(redactor),
+            sanitize,
+        })
     }
 
     fn format_event<'ctx, 'evt, 'w, Reg, N>(
@@ -184,7 +572,16 @@ impl FmtorpFormatter {
         Reg: Subscriber + for<'a> LookupSpan<'a>,
         N: for<'a> FormatFields<'a> + 'static,
     {
-        let value_writer = CustomValueWriter { ctx, event };
+        let value_writer = CustomValueWriter {
+            ctx,
+            event,
+            timestamps: &self.timestamps,
+            placed: &self.placed,
+            registered: &self.registered,
+            context: &self.context,
+            redactor: &self.redactor,
+            sanitize: self.sanitize,
+        };
         self.fmtr.write(writer, &value_writer)
     }
 }
@@ -195,6 +592,7 @@ struct SingleFieldVisitor<'w> {
     newline:    bool,
     writer:     Writer<'w>,
     field_name: Cow<'static, str>,
+    sanitize:   Option<Sanitize>,
 }
 impl<'w> SingleFieldVisitor<'w> {
     fn new(newline: bool, writer: Writer<'w>, field_name: impl Into<Cow<'static, str>>) -> Self {
@@ -202,8 +600,15 @@ impl<'w> SingleFieldVisitor<'w> {
             newline,
             writer,
             field_name: field_name.into(),
+            sanitize: None,
         }
     }
+
+    /// Sanitize the value, but not the newline after it.
+    fn sanitized(mut self, sanitize: Option<Sanitize>) -> Self {
+        self.sanitize = sanitize;
+        self
+    }
 }
 impl<'w> Visit for SingleFieldVisitor<'w> {
     // todo(eas): Might be good to come back to this, looks like this is getting
@@ -212,25 +617,411 @@ impl<'w> Visit for SingleFieldVisitor<'w> {
         // eas: bummer to hardcode this but thats how tracing does it
         #[allow(unused_must_use, clippy::use_debug)]
         if field.name() == self.field_name {
+            match self.sanitize {
+                Some(mode) => write!(Sanitizing::new(self.writer.by_ref(), mode), "{value:?}"),
+                None => write!(self.writer, "{value:?}"),
+            };
             if self.newline {
-                writeln!(self.writer, "{value:?}");
-            } else {
-                write!(self.writer, "{value:?}");
+                writeln!(self.writer);
             }
         }
     }
 }
 
-/// We go above and beyond to acquire the local utc offset using
-/// the `utc_offset` crate, hence the custom impl.
-struct UtcOffsetTime;
+/// Whether `name` is a built-in field of custom formats.
+pub fn is_builtin_field(name: &str) -> bool {
+    fields::FIELD_SET.contains(name)
+}
+
+/// Whether `format` writes the fields of spans, which must then be recorded.
+pub fn uses_span_fields(format: &ConfigFormat) -> bool {
+    let s = match format {
+        ConfigFormat::Custom(s) => s.clone(),
+        ConfigFormat::Log4rsPattern(p) => {
+            let Ok(s) = log4rs_pattern::translate(p) else {
+                return false;
+            };
+            s
+        },
+        ConfigFormat::Normal | ConfigFormat::MessageOnly => return false,
+    };
+    trace4rs_fmtorp::Fmtr::new(s, &fields::known()).is_ok_and(|fmtr| {
+        fmtr.fields()
+            .any(|f| f.name == fields::SPAN_FIELDS || f.name == fields::FIELD)
+    })
+}
 
-impl FormatTime for UtcOffsetTime {
+fn invalid_argument(
+    fmtr: &trace4rs_fmtorp::Fmtr<'_>,
+    field: Field<'_>,
+    reason: &str,
+) -> trace4rs_fmtorp::Error {
+    fmtr.error_at(field.index, trace4rs_fmtorp::Error::InvalidArgument {
+        field:  field.name.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// How timestamps are written, parsed once from a logger's `timestamp` or a
+/// `{T:<format>}` field.
+#[derive(Clone, Debug, Default)]
+pub enum TimestampFormat {
+    #[default]
+    Rfc3339,
+    Description(OwnedFormatItem),
+}
+
+impl TimestampFormat {
+    /// Parse a `time` format description, or one of the shorthands `rfc3339`,
+    /// `epoch` (seconds) and `epoch_ms` (milliseconds).
+    ///
+    /// # Errors
+    /// `s` isn't a valid format description.
+    pub fn parse(s: &str) -> Result<Self, InvalidFormatDescription> {
+        let description = match s {
+            "rfc3339" => return Ok(Self::Rfc3339),
+            "epoch" => "[unix_timestamp]",
+            "epoch_ms" => "[unix_timestamp precision:millisecond]",
+            s => s,
+        };
+        format_description::parse_owned::<1>(description).map(Self::Description)
+    }
+}
+
+/// Timestamps in a logger's time zone and format. We go above and beyond to
+/// acquire the local utc offset using the `utc_offset` crate, see
+/// `offset::local_offset` for how it is cached.
+#[derive(Clone, Debug, Default)]
+pub struct Timestamp {
+    timezone: Timezone,
+    format:   TimestampFormat,
+}
+
+impl FormatTime for Timestamp {
     fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
-        use tracing_subscriber::fmt::time::OffsetTime;
+        let now = OffsetDateTime::now_utc();
+        let offset = match self.timezone {
+            Timezone::Local => local_offset(now),
+            Timezone::Utc => UtcOffset::UTC,
+            Timezone::Fixed(secs) => UtcOffset::from_whole_seconds(secs).unwrap_or(UtcOffset::UTC),
+        };
+        let now = now.to_offset(offset);
+        let formatted = match &self.format {
+            TimestampFormat::Rfc3339 => now.format(&Rfc3339),
+            TimestampFormat::Description(d) => now.format(d),
+        };
+        w.write_str(&formatted.map_err(|_| fmt::Error)?)
+    }
+}
+
+/// Writes the value of the field `name`: strings as they are, anything else by
+/// its `Debug` representation.
+struct NamedFieldVisitor<'n, 'w> {
+    name:   &'n str,
+    writer: Writer<'w>,
+    found:  bool,
+    result: fmt::Result,
+}
+impl<'n, 'w> NamedFieldVisitor<'n, 'w> {
+    fn new(name: &'n str, writer: Writer<'w>) -> Self {
+        Self {
+            name,
+            writer,
+            found: false,
+            result: Ok(()),
+        }
+    }
+}
+impl Visit for NamedFieldVisitor<'_, '_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if field.name() == self.name && !self.found {
+            self.found = true;
+            self.result = self.writer.write_str(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == self.name && !self.found {
+            self.found = true;
+            #[allow(clippy::use_debug)] // the value's only representation
+            let res = write!(self.writer, "{value:?}");
+            self.result = res;
+        }
+    }
+}
+
+/// Writes the fields like `{f}` does, leaving out those in `skip`.
+struct RestVisitor<'s, 'w> {
+    skip:   &'s [String],
+    writer: Writer<'w>,
+    first:  bool,
+    result: fmt::Result,
+}
+impl<'s, 'w> RestVisitor<'s, 'w> {
+    fn new(skip: &'s [String], writer: Writer<'w>) -> Self {
+        Self {
+            skip,
+            writer,
+            first: true,
+            result: Ok(()),
+        }
+    }
+}
+impl Visit for RestVisitor<'_, '_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        let name = field.name();
+        if self.result.is_err() || name.starts_with("log.") || self.skip.iter().any(|s| s == name)
+        {
+            return;
+        }
+        let sep = if self.first { "" } else { " " };
+        self.first = false;
+        #[allow(clippy::use_debug)] // as `{f}` writes them
+        let res = if name == MESSAGE_FIELD_NAME {
+            write!(self.writer, "{sep}{value:?}")
+        } else {
+            write!(self.writer, "{sep}{name}={value:?}")
+        };
+        self.result = res;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        sync::Arc,
+        thread,
+    };
+
+    use parking_lot::Mutex;
+    use tracing::{
+        span,
+        Subscriber,
+    };
+    use tracing_subscriber::{
+        fmt::{
+            Layer as FmtLayer,
+            MakeWriter,
+        },
+        layer::{
+            Context,
+            SubscriberExt,
+        },
+        registry::LookupSpan,
+        Layer,
+        Registry,
+    };
+
+    use super::EventFormatter;
+    use crate::{
+        config::{
+            Format,
+            Timezone,
+        },
+        subscriber::{
+            redact::{
+                RedactedFields,
+                Redactor,
+            },
+            span_fields,
+        },
+    };
 
-        let (o, _) = utc_offset::get_utc_offset();
-        let t = OffsetTime::new(o, TIME_FORMAT);
-        t.format_time(w)
+    /// Collects the formatted events.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    impl MakeWriter<'_> for Output {
+        type Writer = Self;
+
+        fn make_writer(&self) -> Self {
+            self.clone()
+        }
+    }
+
+    /// Records the span fields, as `T4Layer` does for formats which write
+    /// them.
+    struct SpanFields;
+    impl<S> Layer<S> for SpanFields
+    where
+        S: Subscriber + for<'s> LookupSpan<'s>,
+    {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+            span_fields::on_new_span(attrs, id, &ctx, &Redactor::default());
+        }
+
+        fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+            span_fields::on_record(id, values, &ctx, &Redactor::default());
+        }
+    }
+
+    /// The output of `formatter` for the events logged by `log`.
+    fn format_with(formatter: EventFormatter, log: impl FnOnce()) -> String {
+        let output = Output::default();
+        let fmt_layer = FmtLayer::default()
+            .fmt_fields(RedactedFields::default())
+            .event_format(formatter)
+            .with_ansi(false)
+            .with_writer(output.clone());
+        let subscriber = Registry::default().with(SpanFields).with(fmt_layer);
+        tracing::subscriber::with_default(subscriber, log);
+        let buf = output.0.lock();
+        String::from_utf8(buf.clone()).unwrap()
+    }
+
+    /// The output of the custom format `pattern` for the events logged by
+    /// `log`.
+    fn format(pattern: &str, log: impl FnOnce()) -> String {
+        format_with(Format::Custom(pattern.to_string()).into(), log)
+    }
+
+    #[test]
+    fn timezone() {
+        let zoned = |timezone| {
+            let formatter = EventFormatter::new(
+                Format::Custom("{T} {T(utc)}".to_string()),
+                timezone,
+                None,
+                Arc::default(),
+                Arc::default(),
+                None,
+            );
+            let line = format_with(formatter, || tracing::info!("hello"));
+            let (logger, field) = line.trim_end().split_once(' ').unwrap();
+            (logger.to_string(), field.to_string())
+        };
+        let (utc_logger, utc_field) = zoned(Timezone::Utc);
+        assert!(utc_logger.ends_with('Z'), "{utc_logger}");
+        assert!(utc_field.ends_with('Z'), "{utc_field}");
+        let (fixed_logger, fixed_field) = zoned("+02:00".parse().unwrap());
+        assert!(fixed_logger.ends_with("+02:00"), "{fixed_logger}");
+        assert!(fixed_field.ends_with('Z'), "{fixed_field}");
+    }
+
+    #[test]
+    fn padding() {
+        let content = format("{l:<5}|{m:*^9}|{T(utc):[year]:>6}", || {
+            tracing::info!("héllo");
+            tracing::warn!("日本");
+        });
+        let lines: Vec<&str> = content.lines().collect();
+        let year = lines.first().unwrap().rsplit('|').next().unwrap();
+        assert_eq!(year.len(), 6, "{year}");
+        assert_eq!(lines, [
+            format!("INFO |**héllo**|{year}"),
+            format!("WARN |**日本***|{year}"),
+        ]);
+    }
+
+    #[test]
+    fn location_fields() {
+        let pattern = "{module} {file}:{line}{n}{thread} {thread_id} {pid} {hostname}";
+        let (content, line) = thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(|| {
+                let content = format(pattern, || tracing::info!("located"));
+                (content, line!() - 1)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let mut lines = content.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("{} {}:{line}", module_path!(), file!())
+        );
+        let words: Vec<&str> = lines.next().unwrap().split(' ').collect();
+        let ["worker", thread_id, pid, hostname] = words.as_slice() else {
+            panic!("{content}");
+        };
+        assert!(thread_id.parse::<u64>().is_ok(), "{thread_id}");
+        assert_eq!(*pid, std::process::id().to_string());
+        assert!(!hostname.is_empty(), "{content}");
+    }
+
+    #[test]
+    fn named_fields() {
+        let pattern = "{f.request_id:default=-} {f.user:<6}|{f.attempt:default=0}|{m} [{f:rest}]";
+        let content = format(pattern, || {
+            tracing::info!(user = "bob", attempt = 2, "login");
+            tracing::info_span!("request", request_id = 7).in_scope(|| {
+                tracing::info!(user = "amy", "from the span");
+            });
+        });
+        assert_eq!(
+            content,
+            "- bob   |2|login [login]\n7 amy   |0|from the span [from the span]\n"
+        );
+    }
+
+    #[test]
+    fn conditional_groups() {
+        let content = format("{m}{? [user={f.user}{?, try {f.attempt}}]}", || {
+            tracing::info!("anonymous");
+            tracing::info!(user = "bob", "login");
+            tracing::info!(user = "amy", attempt = 2, "retry");
+        });
+        assert_eq!(
+            content,
+            "anonymous\nlogin [user=bob]\nretry [user=amy, try 2]\n"
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let content = format(r"{{{m}}} \{x\} a\\b c\d", || tracing::info!("hi"));
+        assert_eq!(content, "{hi} {x} a\\b c\\d\n");
+    }
+
+    #[test]
+    fn log4rs_pattern() {
+        let pattern = "{d(%Y)(utc)} {h({l}):<5} {M} {X(user)(-)} {X(shard)(0)} - {m}{n}";
+        let content = format_with(Format::Log4rsPattern(pattern.to_string()).into(), || {
+            tracing::info!(user = "bob", "login");
+            tracing::warn!("anonymous");
+        });
+        let year = time::OffsetDateTime::now_utc().year();
+        let module = module_path!();
+        assert_eq!(
+            content,
+            format!("{year} INFO  {module} bob 0 - login\n{year} WARN  {module} - 0 - anonymous\n")
+        );
+    }
+
+    #[test]
+    fn register_field() {
+        use std::sync::atomic::{
+            AtomicUsize,
+            Ordering,
+        };
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+        crate::register_field("test-region", |mut w| w.write_str("eu-west-1")).unwrap();
+        crate::register_field("test_seq", move |mut w| {
+            let n = counter.fetch_add(1, Ordering::Relaxed);
+            write!(w, "{n}")
+        })
+        .unwrap();
+        assert!(crate::register_field("m", |_| Ok(())).is_err());
+        assert!(crate::register_field("a:b", |_| Ok(())).is_err());
+
+        let content = format("{test-region} #{test_seq} {m}", || {
+            tracing::info!("one");
+            tracing::info!("two");
+        });
+        assert_eq!(content, "eu-west-1 #0 one\neu-west-1 #1 two\n");
+        assert_eq!(count.load(Ordering::Relaxed), 2);
     }
 }
@@ -1,5 +1,11 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
 use tracing::{
-    metadata::LevelFilter,
+    span,
+    Dispatch,
     Event,
     Subscriber,
 };
@@ -12,26 +18,57 @@ use tracing_subscriber::{
 };
 
 use super::{
-    formatter::EventFormatter,
+    context::ContextFields,
+    event_key::event_key,
+    formatted::{
+        FormatIds,
+        Formatted,
+    },
+    formatter,
     logger::Logger,
+    rate_limit::{
+        self,
+        SummaryTicker,
+    },
+    redact::Redactor,
+    sifted,
+    span_fields,
+    span_log::{
+        self,
+        SpanLogs,
+    },
 };
 use crate::{
     appenders::{
+        with_event_key,
+        with_field_values,
         Appender,
         Appenders,
     },
     config::{
+        self,
         AppenderId,
         Config,
+        Target,
     },
     error::Result,
 };
 
 pub struct T4Layer<S = Registry> {
-    enabled:   bool,
-    default:   Logger<S>,
-    loggers:   Vec<Logger<S>>,
-    appenders: Appenders,
+    enabled:     bool,
+    default:     Logger<S>,
+    loggers:     Vec<Logger<S>>,
+    appenders:   Appenders,
+    /// The fields sifting appenders pick their files by.
+    sifted:      Vec<String>,
+    /// Whether an appender collapses repeated events, which are then keyed.
+    dedupe:      bool,
+    spans:       SpanLogs,
+    /// Whether a format writes the fields of spans.
+    span_fields: bool,
+    context:     Arc<ContextFields>,
+    redactor:    Arc<Redactor>,
+    summaries:   SummaryTicker,
 }
 
 impl<S> T4Layer<S> {
@@ -45,6 +82,24 @@ impl<S> T4Layer<S> {
         &self.appenders
     }
 
+    pub fn context(&self) -> &ContextFields {
+        &self.context
+    }
+
+    pub fn summaries(&self) -> &SummaryTicker {
+        &self.summaries
+    }
+
+    /// The number of events dropped by sampling for each logger which samples,
+    /// the default logger is keyed by `None`.
+    pub fn sampled_out_counts(&self) -> HashMap<Option<Target>, u64> {
+        self.loggers
+            .iter()
+            .chain([&self.default])
+            .filter_map(|l| Some((l.target().cloned(), l.sampled_out()?)))
+            .collect()
+    }
+
     /// Disable this subscriber.
     pub fn disable(&mut self) {
         self.enabled = false;
@@ -66,84 +121,191 @@ where
         let stdout_appender = AppenderId("stdout".to_string());
         let appenders =
             Appenders::new(literally::hmap! {stdout_appender.clone() => Appender::new_console()});
+        let context = Arc::default();
+        let redactor = Arc::default();
         let default = Logger::new(
-            LevelFilter::INFO,
             None,
-            [stdout_appender].iter(),
+            &config::Logger::new(config::LevelFilter::INFO, [stdout_appender]),
             &appenders,
-            EventFormatter::Normal,
+            &context,
+            &redactor,
         );
 
-        Self::new(default, vec![], appenders)
+        Self::new(
+            default,
+            vec![],
+            appenders,
+            SpanLogs::default(),
+            context,
+            redactor,
+        )
     }
 
     /// Create a new `Layers` from a default layer and a pre-generated vec of
     /// sub-layers.
-    fn new(default: Logger<Reg>, loggers: Vec<Logger<Reg>>, appenders: Appenders) -> Self {
+    fn new(
+        mut default: Logger<Reg>,
+        mut loggers: Vec<Logger<Reg>>,
+        appenders: Appenders,
+        mut spans: SpanLogs,
+        context: Arc<ContextFields>,
+        redactor: Arc<Redactor>,
+    ) -> Self {
+        let mut format_ids = FormatIds::default();
+        for logger in loggers.iter_mut().chain([&mut default]) {
+            let id = format_ids.id(
+                logger.format(),
+                logger.timezone(),
+                logger.timestamp(),
+                logger.sanitize(),
+            );
+            logger.set_format_id(id);
+        }
+        spans.set_format_ids(&mut format_ids);
+        let span_fields = loggers
+            .iter()
+            .chain([&default])
+            .map(Logger::format)
+            .chain(spans.formats())
+            .any(formatter::uses_span_fields);
+        let limiters = loggers
+            .iter()
+            .chain([&default])
+            .filter_map(|l| l.rate_limiter().cloned())
+            .collect();
+
         Self {
             enabled: true,
             default,
             loggers,
+            sifted: appenders.sifted_fields(),
+            dedupe: appenders.dedupes(),
             appenders,
+            spans,
+            span_fields,
+            context,
+            redactor,
+            summaries: SummaryTicker::new(limiters),
         }
     }
 
     /// Generate a `Layers` from a config and back it with `broker`.
     ///
     /// # Errors
-    /// An error may occur while building the appenders.
+    /// An error may occur while building the appenders or the redaction
+    /// rules.
     pub fn from_config(config: &Config) -> Result<Self> {
         let appenders = (&config.appenders).try_into()?;
+        let context = Arc::new(ContextFields::new(&config.context));
+        let redactor = Arc::new(Redactor::new(&config.redact)?);
         let layers: Vec<Logger<_>> = config
             .loggers
             .iter()
-            .map(|(targ, lg)| {
-                Logger::new(
-                    lg.level.into(),
-                    Some(targ.clone()),
-                    lg.appenders.iter(),
-                    &appenders,
-                    lg.format.clone().into(),
-                )
-            })
+            .map(|(targ, lg)| Logger::new(Some(targ.clone()), lg, &appenders, &context, &redactor))
             .collect();
 
-        let default = Logger::new(
-            config.default.level.into(),
-            None,
-            config.default.appenders.iter(),
-            &appenders,
-            config.default.format.clone().into(),
-        );
+        let default = Logger::new(None, &config.default, &appenders, &context, &redactor);
 
-        Ok(T4Layer::new(default, layers, appenders))
+        Ok(T4Layer::new(
+            default,
+            layers,
+            appenders,
+            SpanLogs::new(&config.spans, Arc::clone(&context), Arc::clone(&redactor)),
+            context,
+            redactor,
+        ))
     }
 }
 
-impl<S> Layer<S> for T4Layer<S>
+impl<S> T4Layer<S>
 where
     S: Subscriber + for<'s> LookupSpan<'s>,
 {
-    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
-        if !self.enabled {
-            return;
-        }
+    fn dispatch(&self, event: &Event<'_>, ctx: Context<'_, S>) {
         let mut any = false;
         let normalized_metadata = NormalizeEvent::normalized_metadata(event);
         let metadata = normalized_metadata
             .as_ref()
             .unwrap_or_else(|| event.metadata());
 
+        let normalized = normalized_metadata.is_some();
+
+        // loggers sharing a format share the formatted event
+        let mut formatted = Formatted::new();
+
         for layer in &self.loggers {
             let enabled = layer.enabled(metadata, ctx.clone());
             any |= enabled;
-            if enabled {
-                layer.on_event(event, ctx.clone());
+            if enabled && layer.sample(metadata) && layer.rate_limit(metadata, normalized, &ctx) {
+                layer.write_event(event, ctx.clone(), &mut formatted);
             }
         }
+        if !self.spans.is_empty() {
+            span_log::on_event(event, metadata, &ctx, &mut formatted);
+        }
         // If no other layer logged this then the default one will
-        if !any && self.default.enabled(metadata, ctx.clone()) {
-            self.default.on_event(event, ctx);
+        if !any
+            && self.default.enabled(metadata, ctx.clone())
+            && self.default.sample(metadata)
+            && self.default.rate_limit(metadata, normalized, &ctx)
+        {
+            self.default.write_event(event, ctx, &mut formatted);
+        }
+    }
+}
+
+impl<S> Layer<S> for T4Layer<S>
+where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.summaries.start(subscriber.downgrade());
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if !self.sifted.is_empty() {
+            sifted::on_new_span(&self.sifted, attrs, id, &ctx, &self.redactor);
+        }
+        // a disabled layer opens no span log files
+        if self.enabled && !self.spans.is_empty() {
+            self.spans.on_new_span(attrs, id, &ctx);
+        }
+        if self.span_fields {
+            span_fields::on_new_span(attrs, id, &ctx, &self.redactor);
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if !self.sifted.is_empty() {
+            sifted::on_record(&self.sifted, id, values, &ctx, &self.redactor);
+        }
+        if self.span_fields {
+            span_fields::on_record(id, values, &ctx, &self.redactor);
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        if !self.spans.is_empty() {
+            span_log::on_close(&id, &ctx);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !self.enabled {
+            return;
+        }
+        if rate_limit::summarizing() {
+            for logger in self.loggers.iter().chain([&self.default]) {
+                logger.write_summary(event, ctx.clone());
+            }
+            return;
+        }
+        let key = self.dedupe.then(|| event_key(event));
+        if self.sifted.is_empty() {
+            with_event_key(key, || self.dispatch(event, ctx));
+        } else {
+            let values = sifted::event_values(&self.sifted, event, &ctx, &self.redactor);
+            with_field_values(values, || with_event_key(key, || self.dispatch(event, ctx)));
         }
     }
 }
@@ -0,0 +1,339 @@
+//! The log4rs pattern language, translated to custom formats so that patterns
+//! can be brought along when migrating from log4rs.
+use std::{
+    iter::Peekable,
+    str::Chars,
+};
+
+use trace4rs_fmtorp::{
+    Error,
+    Padding,
+};
+
+/// A part of a translated pattern.
+enum Out {
+    Text(String),
+    Field {
+        name: String,
+        arg:  Option<String>,
+        spec: Option<String>,
+    },
+}
+
+/// Translate a log4rs pattern such as
+/// `{d(%Y-%m-%d %H:%M:%S)} {h({l})} {M}:{L} - {m}{n}` to a custom format.
+///
+/// Supported are `d`/`date` with a strftime format and `utc` or `local`,
+/// `l`/`level`, `m`/`message`, `M`/`module`, `L`/`line`, `f`/`file`,
+/// `T`/`thread`, `I`/`thread_id`, `P`/`pid`, `t`/`target`, `n`,
+/// `X`/`mdc` and `K`/`key_value` read from the fields of the event and its
+/// spans, `h`/`highlight` (written without colour), `D`/`debug`,
+/// `R`/`release`, `(...)` groups and width specs like `{l:<5.5}`. Every event
+/// ends with a newline, so a trailing `{n}` is dropped.
+///
+/// # Errors
+/// If the pattern is malformed or uses a formatter we don't support.
+pub fn translate(pattern: &str) -> Result<String, Error> {
+    let mut out = parse(pattern)?;
+    if matches!(out.last(), Some(Out::Field { name, spec: None, .. }) if name == "n") {
+        out.pop();
+    }
+    let mut s = String::with_capacity(pattern.len());
+    for o in out {
+        match o {
+            Out::Text(text) => {
+                for c in text.chars() {
+                    if matches!(c, '{' | '}' | '\\') {
+                        s.push('\\');
+                    }
+                    s.push(c);
+                }
+            },
+            Out::Field { name, arg, spec } => {
+                s.push('{');
+                s.push_str(&name);
+                if let Some(arg) = &arg {
+                    if arg.contains(['{', '}', '\\']) {
+                        return Err(invalid(&name, "braces and backslashes aren't supported"));
+                    }
+                    s.push(':');
+                    s.push_str(arg);
+                }
+                let reads_as_spec = arg
+                    .as_deref()
+                    .and_then(|a| a.rsplit(':').next())
+                    .is_some_and(|a| Padding::parse(a).is_some());
+                match spec {
+                    Some(spec) => {
+                        s.push(':');
+                        s.push_str(&spec);
+                    },
+                    // an argument ending in what reads as a width spec needs one of its own
+                    None if reads_as_spec => s.push(':'),
+                    None => {},
+                }
+                s.push('}');
+            },
+        }
+    }
+    Ok(s)
+}
+
+type Input<'a> = Peekable<Chars<'a>>;
+
+fn parse(pattern: &str) -> Result<Vec<Out>, Error> {
+    let mut out = Vec::new();
+    let mut text = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+            '{' => {
+                if !text.is_empty() {
+                    out.push(Out::Text(std::mem::take(&mut text)));
+                }
+                formatter(&mut chars, &mut out)?;
+            },
+            '}' => {
+                return Err(Error::IllegalCharacter(
+                    c,
+                    "found outside a formatter".to_string(),
+                ));
+            },
+            '\\' => text.push(chars.next().unwrap_or(c)),
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        out.push(Out::Text(text));
+    }
+    Ok(out)
+}
+
+/// Parse a formatter, after its `{`.
+fn formatter(chars: &mut Input<'_>, out: &mut Vec<Out>) -> Result<(), Error> {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+        name.push(c);
+    }
+    let mut args = Vec::new();
+    while chars.next_if_eq(&'(').is_some() {
+        args.push(argument(chars)?);
+    }
+    let spec = match chars.next_if_eq(&':') {
+        Some(_) => {
+            let mut spec = String::new();
+            while let Some(c) = chars.next_if(|c| *c != '}') {
+                spec.push(c);
+            }
+            if Padding::parse(&spec).is_none() {
+                return Err(invalid(&name, &format!("invalid width spec '{spec}'")));
+            }
+            Some(spec)
+        },
+        None => None,
+    };
+    if chars.next() != Some('}') {
+        return Err(Error::IllegalCharacter(
+            '{',
+            format!("formatter '{name}' isn't closed"),
+        ));
+    }
+
+    let (field, arg) = match (name.as_str(), args.as_slice()) {
+        ("h" | "highlight" | "", [inner]) => return group(&name, inner, spec, out),
+        ("D" | "debug", [inner]) => {
+            return if cfg!(debug_assertions) {
+                group(&name, inner, spec, out)
+            } else {
+                Ok(())
+            };
+        },
+        ("R" | "release", [inner]) => {
+            return if cfg!(debug_assertions) {
+                Ok(())
+            } else {
+                group(&name, inner, spec, out)
+            };
+        },
+        ("d" | "date", _) => date(&args)?,
+        ("X" | "mdc" | "K" | "key_value", [key]) => (format!("f.{}", unescape(key)), None),
+        ("X" | "mdc" | "K" | "key_value", [key, default]) => {
+            (format!("f.{}", unescape(key)), Some(format!("default={}", unescape(default))))
+        },
+        ("", []) => return Err(Error::EmptyField),
+        (n, []) => {
+            let field = simple(n).ok_or_else(|| Error::UnknownField(name.clone(), None))?;
+            (field.to_string(), None)
+        },
+        (n, _) if simple(n).is_some() => {
+            return Err(invalid(n, "the formatter takes no argument"));
+        },
+        _ => return Err(Error::UnknownField(name, None)),
+    };
+    out.push(Out::Field {
+        name: field,
+        arg,
+        spec,
+    });
+    Ok(())
+}
+
+/// The field of a formatter without arguments.
+fn simple(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "l" | "level" => "l",
+        "m" | "message" => "m",
+        "M" | "module" => "module",
+        "L" | "line" => "line",
+        "f" | "file" => "file",
+        "T" | "thread" => "thread",
+        "I" | "thread_id" => "thread_id",
+        "P" | "pid" => "pid",
+        "t" | "target" => "t",
+        "n" => "n",
+        _ => return None,
+    })
+}
+
+/// Parse an argument, after its `(`, keeping escapes and nested parentheses.
+fn argument(chars: &mut Input<'_>) -> Result<String, Error> {
+    let mut arg = String::new();
+    let mut depth = 0_usize;
+    while let Some(c) = chars.next() {
+        match c {
+            ')' if depth == 0 => return Ok(arg),
+            ')' => depth = depth.saturating_sub(1),
+            '(' => depth = depth.saturating_add(1),
+            '\\' => {
+                arg.push(c);
+                if let Some(escaped) = chars.next() {
+                    arg.push(escaped);
+                }
+                continue;
+            },
+            _ => {},
+        }
+        arg.push(c);
+    }
+    Err(Error::IllegalCharacter(
+        '(',
+        "argument isn't closed".to_string(),
+    ))
+}
+
+/// The formatters of `inner`, with the width spec applied to its only field.
+fn group(name: &str, inner: &str, spec: Option<String>, out: &mut Vec<Out>) -> Result<(), Error> {
+    let mut parsed = parse(inner)?;
+    if let Some(spec) = spec {
+        match parsed.as_mut_slice() {
+            [Out::Field { spec: s @ None, .. }] => *s = Some(spec),
+            _ => return Err(invalid(name, "a width spec needs a single formatter")),
+        }
+    }
+    out.append(&mut parsed);
+    Ok(())
+}
+
+/// The field and timestamp format of `{d(format)(timezone)}`.
+fn date(args: &[String]) -> Result<(String, Option<String>), Error> {
+    let (format, timezone) = match args {
+        [] => (None, None),
+        [f] => (Some(f), None),
+        [f, tz] => (Some(f), Some(tz.as_str())),
+        _ => return Err(invalid("d", "expected a format and a time zone")),
+    };
+    let field = match timezone {
+        None | Some("local") => "T",
+        Some("utc") => "T(utc)",
+        Some(tz) => return Err(invalid("d", &format!("unknown time zone '{tz}'"))),
+    };
+    let format = format
+        .map(|f| unescape(f))
+        .filter(|f| !f.is_empty())
+        .map(|f| strftime(&f))
+        .transpose()?;
+    Ok((field.to_string(), format))
+}
+
+/// Translate a strftime format to a `time` format description.
+fn strftime(format: &str) -> Result<String, Error> {
+    let mut out = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            if c == '[' {
+                out.push('[');
+            }
+            out.push(c);
+            continue;
+        }
+        // modifiers as in `%.3f`, `%:z` or `%-d` come before the specifier
+        let mut spec = String::new();
+        for m in chars.by_ref() {
+            spec.push(m);
+            if m.is_ascii_alphabetic() || m == '%' {
+                break;
+            }
+        }
+        out.push_str(match spec.as_str() {
+            "Y" => "[year]",
+            "y" => "[year repr:last_two]",
+            "m" => "[month]",
+            "-m" => "[month padding:none]",
+            "b" | "h" => "[month repr:short]",
+            "B" => "[month repr:long]",
+            "d" => "[day]",
+            "-d" => "[day padding:none]",
+            "e" => "[day padding:space]",
+            "a" => "[weekday repr:short]",
+            "A" => "[weekday repr:long]",
+            "u" => "[weekday repr:monday]",
+            "w" => "[weekday repr:sunday one_indexed:false]",
+            "j" => "[ordinal]",
+            "H" => "[hour]",
+            "-H" => "[hour padding:none]",
+            "k" => "[hour padding:space]",
+            "I" => "[hour repr:12]",
+            "l" => "[hour repr:12 padding:space]",
+            "p" => "[period]",
+            "P" => "[period case:lower]",
+            "M" => "[minute]",
+            "S" => "[second]",
+            "f" | "9f" => "[subsecond digits:9]",
+            "3f" => "[subsecond digits:3]",
+            "6f" => "[subsecond digits:6]",
+            ".f" => ".[subsecond]",
+            ".3f" => ".[subsecond digits:3]",
+            ".6f" => ".[subsecond digits:6]",
+            ".9f" => ".[subsecond digits:9]",
+            "z" => "[offset_hour sign:mandatory][offset_minute]",
+            ":z" => "[offset_hour sign:mandatory]:[offset_minute]",
+            "s" => "[unix_timestamp]",
+            "T" => "[hour]:[minute]:[second]",
+            "R" => "[hour]:[minute]",
+            "F" => "[year]-[month]-[day]",
+            "D" => "[month]/[day]/[year repr:last_two]",
+            "%" => "%",
+            _ => return Err(invalid("d", &format!("unsupported specifier '%{spec}'"))),
+        });
+    }
+    Ok(out)
+}
+
+fn unescape(arg: &str) -> String {
+    let mut out = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        out.push(if c == '\\' { chars.next().unwrap_or(c) } else { c });
+    }
+    out
+}
+
+fn invalid(field: &str, reason: &str) -> Error {
+    Error::InvalidArgument {
+        field:  field.to_string(),
+        reason: reason.to_string(),
+    }
+}
@@ -1,5 +1,5 @@
 #![allow(clippy::single_char_lifetime_names)]
-use std::io;
+use std::sync::Arc;
 
 use tracing::{
     metadata::LevelFilter,
@@ -8,58 +8,103 @@ use tracing::{
     Subscriber,
 };
 use tracing_subscriber::{
-    fmt::{
-        format::DefaultFields,
-        writer::{
-            BoxMakeWriter,
-            MakeWriterExt,
-        },
-        Layer as FmtLayer,
-    },
+    fmt::Layer as FmtLayer,
     layer::Context,
     registry::LookupSpan,
     Layer,
     Registry,
 };
 
-use super::formatter::EventFormatter;
+use super::{
+    context::ContextFields,
+    formatted::{
+        Capture,
+        Formatted,
+    },
+    formatter::EventFormatter,
+    rate_limit::{
+        self,
+        Decision,
+        RateLimiter,
+    },
+    redact::{
+        RedactedFields,
+        Redactor,
+    },
+    sample::Sampler,
+};
 use crate::{
-    appenders::Appenders,
+    appenders::{
+        Appenders,
+        FanOut,
+    },
     config::{
+        self,
         AppenderId,
+        Format,
+        Sanitize,
         Target,
+        Timezone,
     },
 };
 
-pub struct Logger<Reg = Registry, N = DefaultFields, F = EventFormatter> {
-    level:  LevelFilter,
-    target: Option<Target>,
-    layer:  FmtLayer<Reg, N, F, BoxMakeWriter>,
+pub struct Logger<Reg = Registry, N = RedactedFields, F = EventFormatter> {
+    level:      LevelFilter,
+    target:     Option<Target>,
+    format:     Format,
+    timezone:   Timezone,
+    timestamp:  Option<String>,
+    sanitize:   Option<Sanitize>,
+    /// Loggers with the same format id share the formatted event.
+    format_id:  Option<usize>,
+    layer:      FmtLayer<Reg, N, F, Capture>,
+    writer:     Option<FanOut>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    sampler:    Option<Sampler>,
 }
 
 impl<Reg> Logger<Reg>
 where
     Reg: Subscriber + for<'s> LookupSpan<'s>,
 {
-    pub fn new<'a>(
-        level: LevelFilter,
+    pub fn new(
         target: Option<Target>,
-        ids: impl Iterator<Item = &'a AppenderId>,
+        config: &config::Logger,
         appenders: &Appenders,
-        format: EventFormatter,
+        context: &Arc<ContextFields>,
+        redactor: &Arc<Redactor>,
     ) -> Logger<Reg>
     where
         Reg: Subscriber + for<'s> LookupSpan<'s>,
     {
-        let writer = mk_writer(ids, appenders).unwrap_or_else(|| BoxMakeWriter::new(io::sink));
-
-        let fmt_layer = FmtLayer::default().event_format(format).with_ansi(false);
-        let layer = fmt_layer.with_writer(writer);
+        let fmt_layer = FmtLayer::default()
+            .fmt_fields(RedactedFields::new(Arc::clone(redactor)))
+            .event_format(EventFormatter::new(
+                config.format.clone(),
+                config.timezone,
+                config.timestamp.as_deref(),
+                Arc::clone(context),
+                Arc::clone(redactor),
+                config.sanitize,
+            ))
+            .with_ansi(false);
+        let layer = fmt_layer.with_writer(Capture);
 
         Logger {
-            level,
+            level: config.level.into(),
             target,
+            format: config.format.clone(),
+            timezone: config.timezone,
+            timestamp: config.timestamp.clone(),
+            sanitize: config.sanitize,
+            format_id: None,
             layer,
+            writer: mk_writer(config.appenders.iter(), appenders),
+            rate_limit: config
+                .rate_limit
+                .as_ref()
+                .map(|limit| Arc::new(RateLimiter::new(limit))),
+            sampler: config.sample.as_ref().map(Sampler::new),
         }
     }
 }
@@ -74,35 +119,122 @@ impl<Reg, N, F> Logger<Reg, N, F> {
 
         match_level && match_target
     }
+
+    pub fn target(&self) -> Option<&Target> {
+        self.target.as_ref()
+    }
+
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    pub fn timezone(&self) -> Timezone {
+        self.timezone
+    }
+
+    pub fn timestamp(&self) -> Option<&str> {
+        self.timestamp.as_deref()
+    }
+
+    pub fn sanitize(&self) -> Option<Sanitize> {
+        self.sanitize
+    }
+
+    /// Share the formatted events with the other loggers given `id`, which
+    /// must have the same format, time zone, timestamp format and
+    /// sanitization.
+    pub fn set_format_id(&mut self, id: usize) {
+        self.format_id = Some(id);
+    }
+
+    /// Whether the event survives sampling, if any.
+    pub fn sample(&self, meta: &Metadata<'_>) -> bool {
+        self.sampler.as_ref().map_or(true, |s| s.keep(meta))
+    }
+
+    /// The rate limiter of the logger, if it has a rate limit.
+    pub fn rate_limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.rate_limit.as_ref()
+    }
+
+    /// The number of events dropped by sampling, if the logger samples.
+    pub fn sampled_out(&self) -> Option<u64> {
+        self.sampler.as_ref().map(Sampler::sampled_out)
+    }
+}
+
+impl<Reg, N, F> Logger<Reg, N, F>
+where
+    Reg: Subscriber + for<'a> LookupSpan<'a>,
+    FmtLayer<Reg, N, F, Capture>: Layer<Reg>,
+{
+    /// Whether the event passes the rate limit, if any. Summaries of the
+    /// events suppressed so far are logged first, see `RateLimiter::check`.
+    pub fn rate_limit(
+        &self,
+        meta: &Metadata<'_>,
+        normalized: bool,
+        ctx: &Context<'_, Reg>,
+    ) -> bool {
+        let Some(limiter) = &self.rate_limit else {
+            return true;
+        };
+        let Decision { pass, summaries } = limiter.check(meta, normalized);
+        for (source, suppressed) in summaries {
+            rate_limit::with_summary(suppressed, &source, |summary| {
+                self.write_event(summary, ctx.clone(), &mut Formatted::new());
+            });
+        }
+        pass
+    }
+
+    /// Write `event` if it is a summary dispatched for this logger's rate
+    /// limiter, see `rate_limit::SummaryTicker`.
+    pub fn write_summary(&self, event: &Event<'_>, ctx: Context<'_, Reg>) {
+        if self.rate_limit.as_ref().is_some_and(rate_limit::is_summarizing) {
+            self.write_event(event, ctx, &mut Formatted::new());
+        }
+    }
 }
 
 impl<Reg, N, F> Layer<Reg> for Logger<Reg, N, F>
 where
     Reg: Subscriber + for<'a> LookupSpan<'a>,
-    FmtLayer<Reg, N, F, BoxMakeWriter>: Layer<Reg>,
+    FmtLayer<Reg, N, F, Capture>: Layer<Reg>,
 {
     fn enabled(&self, meta: &Metadata<'_>, _ctx: Context<'_, Reg>) -> bool {
         Logger::is_enabled(self, meta)
     }
 
     fn on_event(&self, event: &Event<'_>, ctx: Context<'_, Reg>) {
-        self.layer.on_event(event, ctx);
+        self.write_event(event, ctx, &mut Formatted::new());
+    }
+}
+
+impl<Reg, N, F> Logger<Reg, N, F>
+where
+    Reg: Subscriber + for<'a> LookupSpan<'a>,
+    FmtLayer<Reg, N, F, Capture>: Layer<Reg>,
+{
+    /// Write `event` to the appenders, formatting it unless a logger with the
+    /// same format already has.
+    pub fn write_event(&self, event: &Event<'_>, ctx: Context<'_, Reg>, formatted: &mut Formatted) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let buf = formatted.get_or_format(self.format_id, || self.layer.on_event(event, ctx));
+        // failures are counted by the appenders' error handlers
+        let _ = writer.write_event(buf);
     }
 }
 
 fn mk_writer<'a>(
     ids: impl Iterator<Item = &'a AppenderId>,
     appenders: &Appenders,
-) -> Option<BoxMakeWriter> {
-    let mut acc_mw = None;
-    for id in ids {
-        if let Some(appender) = appenders.get(id).map(ToOwned::to_owned) {
-            acc_mw = if let Some(acc) = acc_mw.take() {
-                Some(BoxMakeWriter::new(MakeWriterExt::and(acc, appender)))
-            } else {
-                Some(BoxMakeWriter::new(appender))
-            }
-        }
+) -> Option<FanOut> {
+    let targets: Vec<_> = ids.filter_map(|id| appenders.get(id).cloned()).collect();
+    if targets.is_empty() {
+        return None;
     }
-    acc_mw
+    Some(FanOut::new(targets))
 }
@@ -1,3 +1,16 @@
+mod context;
+mod event_key;
+mod formatted;
 pub mod formatter;
 pub mod layer;
+mod log4rs_pattern;
 pub mod logger;
+pub mod offset;
+pub mod rate_limit;
+mod redact;
+pub mod sample;
+mod sanitize;
+mod sifted;
+mod span_fields;
+mod span_log;
+pub mod user_fields;
@@ -0,0 +1,48 @@
+//! The local UTC offset, looked up once and then only when it may have
+//! changed rather than for every event.
+use std::{
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+use time::{
+    OffsetDateTime,
+    UtcOffset,
+};
+
+/// Offsets change on the quarter hour, in every time zone in use.
+const BOUNDARY_SECS: i64 = 15 * 60;
+
+static REFRESH_SECS: AtomicU64 = AtomicU64::new(60);
+/// The cached offset and the unix time from which it must be looked up again,
+/// together so that neither is ever seen without the other.
+static CACHED: RwLock<Option<(UtcOffset, i64)>> = RwLock::new(None);
+
+/// Set how often the local UTC offset is looked up again, it is also looked
+/// up at each quarter hour when a daylight saving change may take effect. The
+/// default is every minute.
+pub fn set_utc_offset_refresh(interval: Duration) {
+    REFRESH_SECS.store(interval.as_secs().max(1), Ordering::Relaxed);
+    *CACHED.write() = None;
+}
+
+/// The local offset at `now`.
+pub fn local_offset(now: OffsetDateTime) -> UtcOffset {
+    let now = now.unix_timestamp();
+    if let Some((offset, stale_at)) = *CACHED.read() {
+        if now < stale_at {
+            return offset;
+        }
+    }
+    let (offset, _) = utc_offset::get_utc_offset();
+    let interval = i64::try_from(REFRESH_SECS.load(Ordering::Relaxed)).unwrap_or(i64::MAX);
+    let boundary = now
+        .saturating_sub(now.rem_euclid(BOUNDARY_SECS))
+        .saturating_add(BOUNDARY_SECS);
+    *CACHED.write() = Some((offset, now.saturating_add(interval).min(boundary)));
+    offset
+}
@@ -0,0 +1,409 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    sync::{
+        mpsc,
+        Arc,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use parking_lot::Mutex;
+use tracing::{
+    field::display,
+    Dispatch,
+    Event,
+    Level,
+    Metadata,
+};
+use tracing_core::{
+    callsite::{
+        Callsite,
+        Identifier,
+    },
+    dispatcher::WeakDispatch,
+    field::{
+        FieldSet,
+        Value,
+    },
+    identify_callsite,
+    metadata::Kind,
+    subscriber::Interest,
+};
+
+use crate::config::{
+    RateLimit,
+    RateLimitKey,
+};
+
+/// How often the buckets of a `RateLimiter` are swept for suppressed events to
+/// summarize and for buckets to drop.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+thread_local! {
+    /// The limiter whose summary a `SummaryTicker` is dispatching on this
+    /// thread, only the logger it belongs to writes the summary.
+    static SUMMARIZING: Cell<Option<*const RateLimiter>> = const { Cell::new(None) };
+}
+
+/// `RateLimiter` enforces a `config::RateLimit` with a token bucket per
+/// callsite or target.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per:     RateLimitKey,
+    rate:    f64,
+    burst:   f64,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_callsite: HashMap<Identifier, Bucket>,
+    /// Keyed by target, or by location for `log` records which all share a
+    /// handful of callsites.
+    by_name:     HashMap<String, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens:     f64,
+    last:       Instant,
+    suppressed: u64,
+    /// How the source of the events is described in summaries.
+    source:     String,
+}
+
+/// The outcome of checking an event against a `RateLimiter`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Decision {
+    pub pass:      bool,
+    /// The sources with events suppressed since their last summary, with the
+    /// number of those events, to be summarized before the event.
+    pub summaries: Vec<(String, u64)>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: &RateLimit) -> Self {
+        Self {
+            per:     limit.per,
+            rate:    f64::from(limit.rate),
+            burst:   f64::from(limit.burst.max(1)),
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Take a token for an event with the given metadata, `normalized` is set
+    /// when the metadata was normalized from a `log` record.
+    ///
+    /// The suppressed events of a source are summarized once an event from it
+    /// passes again, the others by the `SummaryTicker` of the layer.
+    pub fn check(&self, meta: &Metadata<'_>, normalized: bool) -> Decision {
+        self.check_at(meta, normalized, Instant::now())
+    }
+
+    fn check_at(&self, meta: &Metadata<'_>, normalized: bool, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock();
+        let bucket = match self.per {
+            RateLimitKey::Target => {
+                Self::named(&mut buckets.by_name, meta.target(), self.burst, now, || {
+                    format!("target {}", meta.target())
+                })
+            },
+            // the callsite of a log record is that of its level, not the record
+            RateLimitKey::Callsite if normalized => {
+                let name = describe_callsite(meta);
+                Self::named(&mut buckets.by_name, &name, self.burst, now, || {
+                    format!("callsite {name}")
+                })
+            },
+            RateLimitKey::Callsite => {
+                buckets
+                    .by_callsite
+                    .entry(meta.callsite())
+                    .or_insert_with(|| {
+                        Bucket::full(
+                            self.burst,
+                            now,
+                            format!("callsite {}", describe_callsite(meta)),
+                        )
+                    })
+            },
+        };
+        let pass = bucket.take(self.rate, self.burst, now);
+        let summaries = if pass {
+            bucket.summary().into_iter().collect()
+        } else {
+            Vec::new()
+        };
+        Decision { pass, summaries }
+    }
+
+    /// Take the summaries of all the sources with suppressed events. Buckets
+    /// which refilled with nothing to summarize are dropped, they are no
+    /// different from new ones.
+    fn sweep(&self, now: Instant) -> Vec<(String, u64)> {
+        let mut buckets = self.buckets.lock();
+        let Buckets {
+            by_callsite,
+            by_name,
+        } = &mut *buckets;
+        let mut summaries = Vec::new();
+        let mut sweep = |b: &mut Bucket| {
+            b.refill(self.rate, self.burst, now);
+            summaries.extend(b.summary());
+            b.tokens < self.burst
+        };
+        by_callsite.retain(|_, b| sweep(b));
+        by_name.retain(|_, b| sweep(b));
+        summaries
+    }
+
+    /// Dispatch the summaries of the sweep to `dispatch`, marked as those of
+    /// `limiter`, see `is_summarizing`.
+    fn dispatch_summaries(limiter: &Arc<Self>, dispatch: &Dispatch) {
+        for (source, suppressed) in limiter.sweep(Instant::now()) {
+            with_summary(suppressed, &source, |summary| {
+                SUMMARIZING.with(|s| s.set(Some(Arc::as_ptr(limiter))));
+                dispatch.event(summary);
+                SUMMARIZING.with(|s| s.set(None));
+            });
+        }
+    }
+
+    fn named<'b>(
+        by_name: &'b mut HashMap<String, Bucket>,
+        name: &str,
+        burst: f64,
+        now: Instant,
+        source: impl FnOnce() -> String,
+    ) -> &'b mut Bucket {
+        if !by_name.contains_key(name) {
+            by_name.insert(name.to_string(), Bucket::full(burst, now, source()));
+        }
+        #[allow(clippy::unwrap_used)] // just inserted
+        by_name.get_mut(name).unwrap()
+    }
+}
+
+impl Bucket {
+    fn full(tokens: f64, last: Instant, source: String) -> Self {
+        Self {
+            tokens,
+            last,
+            suppressed: 0,
+            source,
+        }
+    }
+
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = burst.min(elapsed.mul_add(rate, self.tokens));
+        self.last = now;
+    }
+
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> bool {
+        self.refill(rate, burst, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            self.suppressed = self.suppressed.saturating_add(1);
+            false
+        }
+    }
+
+    /// The events suppressed since the last summary, if any.
+    fn summary(&mut self) -> Option<(String, u64)> {
+        match std::mem::take(&mut self.suppressed) {
+            0 => None,
+            n => Some((self.source.clone(), n)),
+        }
+    }
+}
+
+/// Whether the event being dispatched is a summary from a `SummaryTicker`.
+pub fn summarizing() -> bool {
+    SUMMARIZING.with(Cell::get).is_some()
+}
+
+/// Whether the event being dispatched is a summary of `limiter`'s.
+pub fn is_summarizing(limiter: &Arc<RateLimiter>) -> bool {
+    SUMMARIZING
+        .with(Cell::get)
+        .is_some_and(|l| std::ptr::eq(l, Arc::as_ptr(limiter)))
+}
+
+/// `SummaryTicker` sweeps the rate limiters of a layer every
+/// `SWEEP_INTERVAL` on a background thread, so the events suppressed from a
+/// source are reported even if nothing is logged after them.
+///
+/// The summaries are dispatched to the subscriber the layer was registered
+/// with, the thread stops when the ticker is dropped or the subscriber is.
+#[derive(Debug)]
+pub struct SummaryTicker {
+    limiters: Vec<Arc<RateLimiter>>,
+    started:  Mutex<Option<Started>>,
+}
+
+#[derive(Debug)]
+struct Started {
+    dispatch: WeakDispatch,
+    /// Disconnecting the channel wakes and stops the thread.
+    _stop:    mpsc::Sender<()>,
+}
+
+impl SummaryTicker {
+    pub fn new(limiters: Vec<Arc<RateLimiter>>) -> Self {
+        Self {
+            limiters,
+            started: Mutex::default(),
+        }
+    }
+
+    /// Start dispatching summaries to `dispatch`, only the first call has an
+    /// effect.
+    pub fn start(&self, dispatch: WeakDispatch) {
+        let mut started = self.started.lock();
+        if started.is_some() {
+            return;
+        }
+        let (stop, stopped) = mpsc::channel::<()>();
+        if !self.limiters.is_empty() {
+            let limiters = self.limiters.clone();
+            let weak = dispatch.clone();
+            // without the thread summaries still come with passing events
+            let _ = thread::Builder::new()
+                .name("trace4rs-rate-limit".to_string())
+                .spawn(move || {
+                    while let Err(mpsc::RecvTimeoutError::Timeout) =
+                        stopped.recv_timeout(SWEEP_INTERVAL)
+                    {
+                        let Some(subscriber) = weak.upgrade() else {
+                            return;
+                        };
+                        for limiter in &limiters {
+                            RateLimiter::dispatch_summaries(limiter, &subscriber);
+                        }
+                    }
+                });
+        }
+        *started = Some(Started {
+            dispatch,
+            _stop: stop,
+        });
+    }
+
+    /// The subscriber the ticker was started with, for the layer replacing
+    /// this one.
+    pub fn dispatch(&self) -> Option<WeakDispatch> {
+        self.started.lock().as_ref().map(|s| s.dispatch.clone())
+    }
+}
+
+fn describe_callsite(meta: &Metadata<'_>) -> String {
+    match (meta.file(), meta.line()) {
+        (Some(file), Some(line)) => format!("{}:{file}:{line}", meta.target()),
+        _ => format!("{}:{}", meta.target(), meta.name()),
+    }
+}
+
+struct SummaryCallsite;
+static SUMMARY_CALLSITE: SummaryCallsite = SummaryCallsite;
+static SUMMARY_FIELDS: &[&str] = &["message"];
+static SUMMARY_META: Metadata<'static> = Metadata::new(
+    "rate limit summary",
+    "trace4rs",
+    Level::WARN,
+    None,
+    None,
+    None,
+    FieldSet::new(SUMMARY_FIELDS, identify_callsite!(&SUMMARY_CALLSITE)),
+    Kind::EVENT,
+);
+impl Callsite for SummaryCallsite {
+    fn set_interest(&self, _: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'_> {
+        &SUMMARY_META
+    }
+}
+
+/// Build the summary event for `suppressed` events from `source` and hand it
+/// to `f`.
+pub fn with_summary(suppressed: u64, source: &str, f: impl FnOnce(&Event<'_>)) {
+    let fields = SUMMARY_META.fields();
+    #[allow(clippy::unwrap_used)] // the field is declared above
+    let message = fields.field("message").unwrap();
+    let msg = display(format!("suppressed {suppressed} events from {source}"));
+    let values = [(&message, Some(&msg as &dyn Value))];
+    let value_set = fields.value_set(&values);
+    f(&Event::new(&SUMMARY_META, &value_set));
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    use tracing::{
+        Level,
+        Metadata,
+    };
+    use tracing_core::{
+        field::FieldSet,
+        identify_callsite,
+        metadata::Kind,
+    };
+
+    use super::{
+        RateLimiter,
+        SUMMARY_CALLSITE,
+        SWEEP_INTERVAL,
+    };
+    use crate::config::{
+        RateLimit,
+        RateLimitKey,
+    };
+
+    #[test]
+    fn sweep() {
+        let limiter = RateLimiter::new(&RateLimit {
+            rate:  10,
+            burst: 1,
+            per:   RateLimitKey::Target,
+        });
+        let meta = |target| {
+            Metadata::new(
+                "event",
+                target,
+                Level::INFO,
+                None,
+                None,
+                None,
+                FieldSet::new(&[], identify_callsite!(&SUMMARY_CALLSITE)),
+                Kind::EVENT,
+            )
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert!(limiter.check_at(&meta("flood"), false, at(0)).pass);
+        assert!(!limiter.check_at(&meta("flood"), false, at(1)).pass);
+        assert!(!limiter.check_at(&meta("flood"), false, at(2)).pass);
+        assert!(limiter.check_at(&meta("quiet"), false, at(3)).summaries.is_empty());
+
+        assert!(limiter.check_at(&meta("other"), false, at(4)).pass);
+
+        // the flood is summarized, and the refilled buckets dropped
+        let swept = limiter.sweep(at(4) + SWEEP_INTERVAL);
+        assert_eq!(swept, vec![("target flood".to_string(), 2)]);
+        assert!(limiter.buckets.lock().by_name.is_empty());
+        assert!(limiter.sweep(at(5) + SWEEP_INTERVAL).is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! Redaction of sensitive values before events are written, see
+//! `config::Redaction`.
+use std::{
+    borrow::Cow,
+    fmt::{
+        self,
+        Write as _,
+    },
+    sync::Arc,
+};
+
+use hmac::{
+    Hmac,
+    Mac,
+};
+use regex::{
+    Captures,
+    Regex,
+};
+use sha2::Sha256;
+use tracing::field::{
+    Field,
+    Visit,
+};
+use tracing_subscriber::{
+    field::{
+        RecordFields,
+        VisitOutput,
+    },
+    fmt::{
+        format::{
+            DefaultVisitor,
+            Writer,
+        },
+        FormatFields,
+    },
+};
+
+use crate::{
+    config::{
+        Redaction,
+        Replacement,
+    },
+    env::expand_env_vars,
+    error::{
+        Error,
+        Result,
+    },
+};
+
+/// Written instead of masked values.
+const MASK: &str = "***";
+
+/// The number of bytes of the HMAC written for hashed values.
+const HASH_BYTES: usize = 8;
+
+#[derive(Debug)]
+struct Rule {
+    field:   Option<String>,
+    pattern: Option<Regex>,
+    replace: Replace,
+}
+
+/// A `Replacement` with what it needs.
+#[derive(Debug)]
+enum Replace {
+    Remove,
+    Mask,
+    /// Keyed with the contained key.
+    Hash(Vec<u8>),
+}
+
+/// The redaction rules of the config, applied in order.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+}
+impl Redactor {
+    /// # Errors
+    /// If a rule has neither a field nor a pattern, its pattern isn't a valid
+    /// regex, or it hashes without a key.
+    pub fn new(config: &[Redaction]) -> Result<Self> {
+        let rules = config
+            .iter()
+            .map(|r| {
+                if r.field.is_none() && r.pattern.is_none() {
+                    return Err(Error::EmptyRedaction);
+                }
+                let pattern = r
+                    .pattern
+                    .as_deref()
+                    .map(|p| Regex::new(p).map_err(|e| Error::RedactionPattern(p.to_string(), e)))
+                    .transpose()?;
+                let replace = match r.replace {
+                    Replacement::Remove => Replace::Remove,
+                    Replacement::Mask => Replace::Mask,
+                    Replacement::Hash => {
+                        let key = r.key.as_deref().ok_or(Error::RedactionKey)?;
+                        Replace::Hash(expand_env_vars(key).into_owned().into_bytes())
+                    },
+                };
+                Ok(Rule {
+                    field: r.field.clone(),
+                    pattern,
+                    replace,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The `value` of the field `name` with the rules applied, borrowed if
+    /// none changed it and `None` if the field is removed.
+    pub fn redact<'v>(&self, name: &str, value: &'v str) -> Option<Cow<'v, str>> {
+        let mut value = Cow::Borrowed(value);
+        for rule in &self.rules {
+            if rule.field.as_deref().is_some_and(|f| !glob_matches(f, name)) {
+                continue;
+            }
+            match &rule.pattern {
+                Some(pattern) => {
+                    let replaced = pattern.replace_all(&value, |c: &Captures<'_>| {
+                        replacement(&rule.replace, c.get(0).map_or("", |m| m.as_str()))
+                    });
+                    if let Cow::Owned(replaced) = replaced {
+                        value = Cow::Owned(replaced);
+                    }
+                },
+                None if matches!(rule.replace, Replace::Remove) => return None,
+                None => value = Cow::Owned(replacement(&rule.replace, &value)),
+            }
+        }
+        Some(value)
+    }
+
+    /// Record `fields` with `visitor`, the rules applied.
+    pub fn record(&self, fields: impl RecordFields, visitor: &mut impl Visit) {
+        if self.is_empty() {
+            fields.record(visitor);
+        } else {
+            fields.record(&mut Redacting {
+                redactor: self,
+                inner:    visitor,
+            });
+        }
+    }
+}
+
+/// Formats fields as `DefaultFields` does, the rules applied.
+#[derive(Debug, Default)]
+pub struct RedactedFields(Arc<Redactor>);
+impl RedactedFields {
+    pub fn new(redactor: Arc<Redactor>) -> Self {
+        Self(redactor)
+    }
+}
+impl<'w> FormatFields<'w> for RedactedFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'w>, fields: R) -> fmt::Result {
+        let mut visitor = DefaultVisitor::new(writer, true);
+        self.0.record(fields, &mut visitor);
+        visitor.finish()
+    }
+}
+
+/// Passes the fields on to `inner` with the rules applied. Values which no
+/// rule changes are passed on as they are.
+struct Redacting<'r, 'v, V> {
+    redactor: &'r Redactor,
+    inner:    &'v mut V,
+}
+impl<V: Visit> Visit for Redacting<'_, '_, V> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if let Some(value) = self.redactor.redact(field.name(), value) {
+            self.inner.record_str(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        #[allow(clippy::use_debug)] // the value's only representation
+        let formatted = format!("{value:?}");
+        match self.redactor.redact(field.name(), &formatted) {
+            None => {},
+            Some(Cow::Borrowed(_)) => self.inner.record_debug(field, value),
+            Some(Cow::Owned(redacted)) => {
+                self.inner.record_debug(field, &format_args!("{redacted}"));
+            },
+        }
+    }
+}
+
+fn replacement(replace: &Replace, value: &str) -> String {
+    match replace {
+        Replace::Remove => String::new(),
+        Replace::Mask => MASK.to_string(),
+        Replace::Hash(key) => {
+            #[allow(clippy::expect_used)] // infallible
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+            mac.update(value.as_bytes());
+            mac.finalize()
+                .into_bytes()
+                .iter()
+                .take(HASH_BYTES)
+                .fold(String::with_capacity(HASH_BYTES.saturating_mul(2)), |mut s, b| {
+                    let _ = write!(s, "{b:02x}");
+                    s
+                })
+        },
+    }
+}
+
+/// Whether `name` matches `glob`, ignoring ASCII case.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // the position after the last `*` in `glob` and in `name` where it stops
+    let mut star = None;
+    let (mut g, mut n) = (0_usize, 0_usize);
+    while let Some(c) = name.get(n) {
+        match glob.get(g) {
+            Some('*') => {
+                g = g.saturating_add(1);
+                star = Some((g, n));
+            },
+            Some(p) if *p == '?' || p.eq_ignore_ascii_case(c) => {
+                g = g.saturating_add(1);
+                n = n.saturating_add(1);
+            },
+            // let the last `*` match one more character
+            _ => {
+                let Some((after, stop)) = star else {
+                    return false;
+                };
+                let next = stop.saturating_add(1);
+                star = Some((after, next));
+                g = after;
+                n = next;
+            },
+        }
+    }
+    glob.get(g..).is_some_and(|rest| rest.iter().all(|c| *c == '*'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Redactor;
+    use crate::{
+        config::{
+            Redaction,
+            Replacement,
+        },
+        error::Error,
+    };
+
+    fn rule(pattern: &str, replace: Replacement, key: Option<&str>) -> Redaction {
+        Redaction {
+            field: None,
+            pattern: Some(pattern.to_string()),
+            replace,
+            key: key.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(matches!(
+            Redactor::new(&[Redaction {
+                field:   None,
+                pattern: None,
+                replace: Replacement::Mask,
+                key:     None,
+            }]),
+            Err(Error::EmptyRedaction)
+        ));
+        assert!(matches!(
+            Redactor::new(&[rule("(", Replacement::Mask, None)]),
+            Err(Error::RedactionPattern(..))
+        ));
+        assert!(matches!(
+            Redactor::new(&[rule("secret", Replacement::Hash, None)]),
+            Err(Error::RedactionKey)
+        ));
+        Redactor::new(&[rule("secret", Replacement::Hash, Some("pepper"))]).unwrap();
+    }
+}
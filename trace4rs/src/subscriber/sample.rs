@@ -0,0 +1,92 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{
+        BuildHasher,
+        Hasher,
+    },
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+};
+
+use tracing::{
+    metadata::LevelFilter,
+    Metadata,
+};
+
+use crate::config::{
+    Ppm,
+    Sample,
+};
+
+/// `Sampler` decides which of a logger's events are kept, see `config::Sample`.
+#[derive(Debug)]
+pub struct Sampler {
+    level:       LevelFilter,
+    rate:        Option<f64>,
+    every:       Option<u64>,
+    seen:        AtomicU64,
+    sampled_out: AtomicU64,
+}
+impl Sampler {
+    pub fn new(sample: &Sample) -> Self {
+        Self {
+            level:       sample.level.into(),
+            rate:        sample.rate.map(Ppm::fraction),
+            every:       sample.every.map(u64::from),
+            seen:        AtomicU64::new(0),
+            sampled_out: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether the event should be kept, counting it if not.
+    pub fn keep(&self, meta: &Metadata<'_>) -> bool {
+        // more severe than the sampled levels
+        if *meta.level() < self.level {
+            return true;
+        }
+        let keep_every = self.every.map_or(true, |n| {
+            // a period of zero keeps everything, like a period of one
+            self.seen
+                .fetch_add(1, Ordering::Relaxed)
+                .checked_rem(n)
+                .map_or(true, |r| r == 0)
+        });
+        let keep = keep_every && self.rate.map_or(true, |r| random() < r);
+        if !keep {
+            self.sampled_out.fetch_add(1, Ordering::Relaxed);
+        }
+        keep
+    }
+
+    /// The number of events dropped so far.
+    pub fn sampled_out(&self) -> u64 {
+        self.sampled_out.load(Ordering::Relaxed)
+    }
+}
+
+/// A uniformly distributed number in `[0, 1)`, from a per thread xorshift
+/// generator. Sampling doesn't need anything stronger.
+#[allow(clippy::cast_precision_loss)] // the top 53 bits fit the f64 mantissa exactly
+fn random() -> f64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new({
+            let mut h = RandomState::new().build_hasher();
+            h.write_u8(0);
+            // xorshift must not be seeded with zero
+            h.finish() | 1
+        });
+    }
+    let x = STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13_u8;
+        x ^= x >> 7_u8;
+        x ^= x << 17_u8;
+        state.set(x);
+        x
+    });
+    let top = (x >> 11_u8) as f64;
+    top / (1_u64 << 53_u8) as f64
+}
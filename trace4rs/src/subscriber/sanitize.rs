@@ -0,0 +1,46 @@
+//! Escaping of control characters in messages and field values, see
+//! `config::Sanitize`.
+use std::fmt::{
+    self,
+    Write,
+};
+
+use crate::config::Sanitize;
+
+/// Written after each line break in `Sanitize::Indent` mode.
+const INDENT: &str = "    ";
+
+/// Writes to `inner` with control characters escaped as `mode` says.
+pub struct Sanitizing<W> {
+    inner: W,
+    mode:  Sanitize,
+}
+impl<W: Write> Sanitizing<W> {
+    pub fn new(inner: W, mode: Sanitize) -> Self {
+        Self { inner, mode }
+    }
+}
+impl<W: Write> Write for Sanitizing<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut rest = s;
+        while let Some(i) = rest.find(char::is_control) {
+            let (clean, tail) = rest.split_at(i);
+            self.inner.write_str(clean)?;
+            let mut chars = tail.chars();
+            let Some(c) = chars.next() else {
+                break;
+            };
+            match (self.mode, c) {
+                (Sanitize::Indent, '\n') => {
+                    self.inner.write_char('\n')?;
+                    self.inner.write_str(INDENT)?;
+                },
+                // the line break of a CRLF
+                (Sanitize::Indent, '\r') if chars.as_str().starts_with('\n') => {},
+                _ => write!(self.inner, "{}", c.escape_debug())?,
+            }
+            rest = chars.as_str();
+        }
+        self.inner.write_str(rest)
+    }
+}
@@ -0,0 +1,133 @@
+//! Capture of the event and span fields sifting appenders pick their files
+//! by. The values are redacted, since they end up in file names.
+use std::fmt;
+
+use tracing::{
+    field::{
+        Field,
+        Visit,
+    },
+    span,
+    Event,
+    Subscriber,
+};
+use tracing_subscriber::{
+    layer::Context,
+    registry::LookupSpan,
+};
+
+use super::redact::Redactor;
+
+/// The sifted field values recorded on a span.
+struct SpanValues(Vec<(String, String)>);
+
+/// Collects the values of the fields named `names`.
+pub struct Collector<'n> {
+    names:  &'n [String],
+    values: Vec<(String, String)>,
+}
+impl<'n> Collector<'n> {
+    pub fn new(names: &'n [String]) -> Self {
+        Self {
+            names,
+            values: Vec::new(),
+        }
+    }
+
+    pub fn into_values(self) -> Vec<(String, String)> {
+        self.values
+    }
+
+    fn insert(&mut self, field: &Field, value: String) {
+        if !self.names.iter().any(|n| n == field.name()) {
+            return;
+        }
+        match self.values.iter_mut().find(|(n, _)| n == field.name()) {
+            Some((_, v)) => *v = value,
+            None => self.values.push((field.name().to_string(), value)),
+        }
+    }
+}
+impl Visit for Collector<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        #[allow(clippy::use_debug)] // the value's only representation
+        self.insert(field, format!("{value:?}"));
+    }
+}
+
+/// Record the sifted fields of a new span.
+pub fn on_new_span<S>(
+    names: &[String],
+    attrs: &span::Attributes<'_>,
+    id: &span::Id,
+    ctx: &Context<'_, S>,
+    redactor: &Redactor,
+) where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    let mut collector = Collector::new(names);
+    redactor.record(attrs, &mut collector);
+    if let Some(span) = ctx.span(id) {
+        span.extensions_mut().insert(SpanValues(collector.values));
+    }
+}
+
+/// Record sifted fields set on a span after its creation.
+pub fn on_record<S>(
+    names: &[String],
+    id: &span::Id,
+    values: &span::Record<'_>,
+    ctx: &Context<'_, S>,
+    redactor: &Redactor,
+) where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    let Some(span) = ctx.span(id) else {
+        return;
+    };
+    let mut extensions = span.extensions_mut();
+    let Some(SpanValues(recorded)) = extensions.get_mut::<SpanValues>() else {
+        return;
+    };
+    let mut collector = Collector {
+        names,
+        values: std::mem::take(recorded),
+    };
+    redactor.record(values, &mut collector);
+    *recorded = collector.values;
+}
+
+/// The values of the sifted fields for `event`, taken from the event itself or
+/// else the closest enclosing span which has them.
+pub fn event_values<S>(
+    names: &[String],
+    event: &Event<'_>,
+    ctx: &Context<'_, S>,
+    redactor: &Redactor,
+) -> Vec<(String, String)>
+where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    let mut collector = Collector::new(names);
+    redactor.record(event, &mut collector);
+
+    let mut values = collector.values;
+    if values.len() < names.len() {
+        for span in ctx.event_scope(event).into_iter().flatten() {
+            let extensions = span.extensions();
+            let Some(SpanValues(recorded)) = extensions.get::<SpanValues>() else {
+                continue;
+            };
+            for (name, value) in recorded {
+                if !values.iter().any(|(n, _)| n == name) {
+                    values.push((name.clone(), value.clone()));
+                }
+            }
+        }
+    }
+    values
+}
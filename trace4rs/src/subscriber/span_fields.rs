@@ -0,0 +1,118 @@
+//! The fields of spans, kept for `{S.fields}` and `{f.<name>}` in custom
+//! formats.
+use std::fmt;
+
+use tracing::{
+    field::{
+        Field,
+        Visit,
+    },
+    span,
+    Subscriber,
+};
+use tracing_subscriber::{
+    field::{
+        RecordFields,
+        VisitOutput,
+    },
+    fmt::format::{
+        DefaultVisitor,
+        Writer,
+    },
+    layer::Context,
+    registry::LookupSpan,
+};
+
+use super::redact::Redactor;
+
+/// The fields of a span, formatted and by name.
+pub struct SpanFields {
+    pub formatted: String,
+    pub values:    Vec<(&'static str, String)>,
+}
+impl SpanFields {
+    /// The value of the field `name`, if the span has it.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Collects the values of fields: strings as they are, anything else by its
+/// `Debug` representation.
+struct Values<'v>(&'v mut Vec<(&'static str, String)>);
+impl Values<'_> {
+    fn insert(&mut self, field: &Field, value: String) {
+        match self.0.iter_mut().find(|(n, _)| *n == field.name()) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((field.name(), value)),
+        }
+    }
+}
+impl Visit for Values<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        #[allow(clippy::use_debug)] // the value's only representation
+        self.insert(field, format!("{value:?}"));
+    }
+}
+
+/// Record the fields of a new span, redacted.
+pub fn on_new_span<S>(
+    attrs: &span::Attributes<'_>,
+    id: &span::Id,
+    ctx: &Context<'_, S>,
+    redactor: &Redactor,
+) where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    if let Some(span) = ctx.span(id) {
+        let mut values = Vec::new();
+        redactor.record(attrs, &mut Values(&mut values));
+        span.extensions_mut().insert(SpanFields {
+            formatted: format(attrs, redactor),
+            values,
+        });
+    }
+}
+
+/// Record fields set on a span after its creation, redacted.
+pub fn on_record<S>(
+    id: &span::Id,
+    values: &span::Record<'_>,
+    ctx: &Context<'_, S>,
+    redactor: &Redactor,
+) where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    let Some(span) = ctx.span(id) else {
+        return;
+    };
+    let added = format(values, redactor);
+    if added.is_empty() {
+        return;
+    }
+    let mut extensions = span.extensions_mut();
+    if let Some(fields) = extensions.get_mut::<SpanFields>() {
+        if !fields.formatted.is_empty() {
+            fields.formatted.push(' ');
+        }
+        fields.formatted.push_str(&added);
+        redactor.record(values, &mut Values(&mut fields.values));
+    }
+}
+
+/// Format `fields` as `DefaultFields` does.
+fn format(fields: impl RecordFields, redactor: &Redactor) -> String {
+    let mut out = String::new();
+    let mut visitor = DefaultVisitor::new(Writer::new(&mut out), true);
+    redactor.record(fields, &mut visitor);
+    // formatting into a string doesn't fail
+    let _ = visitor.finish();
+    out
+}
@@ -0,0 +1,207 @@
+//! Files of their own for the events within configured spans, see
+//! `config::SpanLog`.
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
+use tracing::{
+    span,
+    Event,
+    Metadata,
+    Subscriber,
+};
+use tracing_subscriber::{
+    layer::Context,
+    registry::LookupSpan,
+    Layer,
+};
+
+use super::{
+    context::ContextFields,
+    formatted::{
+        FormatIds,
+        Formatted,
+    },
+    formatter::EventFormatter,
+    logger::Logger,
+    redact::Redactor,
+    sifted::Collector,
+};
+use crate::{
+    appenders::{
+        fill_template,
+        template_fields,
+        Appender,
+        Appenders,
+    },
+    config::{
+        self,
+        AppenderId,
+        Format,
+        Timezone,
+    },
+};
+
+/// Substituted for the fields a span doesn't have a value for.
+const MISSING_VALUE: &str = "unknown";
+
+/// The logger writing to the file of a span, kept in the span's extensions
+/// until it closes.
+struct SpanFile<S> {
+    logger:   Arc<Logger<S>>,
+    appender: Appender,
+}
+
+struct SpanLog {
+    path:      String,
+    fields:    Vec<String>,
+    level:     config::LevelFilter,
+    format:    Format,
+    format_id: Option<usize>,
+}
+
+/// The configured span logs by span name.
+#[derive(Default)]
+pub struct SpanLogs {
+    by_name:  HashMap<String, SpanLog>,
+    context:  Arc<ContextFields>,
+    redactor: Arc<Redactor>,
+}
+impl SpanLogs {
+    pub fn new(
+        config: &HashMap<String, config::SpanLog>,
+        context: Arc<ContextFields>,
+        redactor: Arc<Redactor>,
+    ) -> Self {
+        let by_name = config
+            .iter()
+            .map(|(name, log)| {
+                // surface a bad format once rather than for every span
+                let format = match &log.format {
+                    Format::Custom(_) | Format::Log4rsPattern(_)
+                        if !matches!(
+                            EventFormatter::from(log.format.clone()),
+                            EventFormatter::Custom(_)
+                        ) =>
+                    {
+                        Format::Normal
+                    },
+                    f => f.clone(),
+                };
+                let log = SpanLog {
+                    fields: template_fields(&log.path),
+                    path: log.path.clone(),
+                    level: log.level,
+                    format,
+                    format_id: None,
+                };
+                (name.clone(), log)
+            })
+            .collect();
+        Self {
+            by_name,
+            context,
+            redactor,
+        }
+    }
+
+    /// Share the formatted events with the loggers of the same format.
+    pub fn set_format_ids(&mut self, ids: &mut FormatIds) {
+        for log in self.by_name.values_mut() {
+            log.format_id = Some(ids.id(&log.format, Timezone::Local, None, None));
+        }
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &Format> {
+        self.by_name.values().map(|log| &log.format)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// Open the file of a new span if it is configured.
+    pub fn on_new_span<S>(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'s> LookupSpan<'s>,
+    {
+        let Some(log) = self.by_name.get(attrs.metadata().name()) else {
+            return;
+        };
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        // the values end up in the path, so they are redacted as for output
+        let mut collector = Collector::new(&log.fields);
+        self.redactor.record(attrs, &mut collector);
+        let path = fill_template(&log.path, &collector.into_values(), MISSING_VALUE);
+
+        let appender = match Appender::new_file(&path) {
+            Ok(a) => a,
+            #[allow(clippy::print_stderr)] // necessary error surfacing
+            Err(e) => {
+                eprintln!("trace4rs: Error opening span log '{path}': {e}");
+                return;
+            },
+        };
+        let appender_id = AppenderId(path);
+        let appenders = Appenders::new(literally::hmap! {appender_id.clone() => appender.clone()});
+        let mut logger_config = config::Logger::new(log.level, [appender_id]);
+        logger_config.format = log.format.clone();
+        let mut logger = Logger::<S>::new(
+            None,
+            &logger_config,
+            &appenders,
+            &self.context,
+            &self.redactor,
+        );
+        if let Some(format_id) = log.format_id {
+            logger.set_format_id(format_id);
+        }
+        span.extensions_mut().insert(SpanFile {
+            logger: Arc::new(logger),
+            appender,
+        });
+    }
+}
+
+/// Write `event` to the files of the spans it is within.
+pub fn on_event<S>(
+    event: &Event<'_>,
+    meta: &Metadata<'_>,
+    ctx: &Context<'_, S>,
+    formatted: &mut Formatted,
+) where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    for span in ctx.event_scope(event).into_iter().flatten() {
+        // don't hold the extensions while formatting, which reads them
+        let logger = span
+            .extensions()
+            .get::<SpanFile<S>>()
+            .map(|f| Arc::clone(&f.logger));
+        if let Some(logger) = logger {
+            if logger.enabled(meta, ctx.clone()) {
+                logger.write_event(event, ctx.clone(), formatted);
+            }
+        }
+    }
+}
+
+/// Flush and close the file of a span.
+pub fn on_close<S>(id: &span::Id, ctx: &Context<'_, S>)
+where
+    S: Subscriber + for<'s> LookupSpan<'s>,
+{
+    let Some(span) = ctx.span(id) else {
+        return;
+    };
+    let file = span.extensions_mut().remove::<SpanFile<S>>();
+    if let Some(file) = file {
+        #[allow(clippy::print_stderr)] // necessary error surfacing
+        if let Err(e) = file.appender.flush_io() {
+            eprintln!("trace4rs: Error flushing span log: {e}");
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Fields registered by the application, e.g. `{region}`, their values
+//! written by closures when events are formatted.
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use tracing_subscriber::fmt::format::Writer;
+
+use super::formatter;
+use crate::error::{
+    Error,
+    Result,
+};
+
+type FieldFn = dyn Fn(Writer<'_>) -> fmt::Result + Send + Sync;
+
+/// Writes the value of a registered field.
+#[derive(Clone)]
+pub struct UserField(Arc<FieldFn>);
+impl UserField {
+    pub fn write(&self, writer: Writer<'_>) -> fmt::Result {
+        (self.0)(writer)
+    }
+}
+impl fmt::Debug for UserField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UserField")
+    }
+}
+
+static FIELDS: Lazy<RwLock<HashMap<&'static str, UserField>>> = Lazy::new(RwLock::default);
+
+/// Register the field `{name}` for custom formats, its value written by
+/// `write` whenever an event is formatted.
+///
+/// ```
+/// trace4rs::register_field("region", |mut w| w.write_str("eu-west-1")).unwrap();
+/// ```
+///
+/// Formats are parsed when a `Handle` is built or its config is reloaded, so
+/// fields must be registered first. Registering a name again replaces its
+/// writer for formats parsed from then on.
+///
+/// # Errors
+/// If `name` is a built-in field or has characters other than alphanumerics,
+/// `_` and `-`.
+pub fn register_field(
+    name: &'static str,
+    write: impl Fn(Writer<'_>) -> fmt::Result + Send + Sync + 'static,
+) -> Result<()> {
+    let invalid = |reason: &str| Err(Error::RegisterField(name.to_string(), reason.to_string()));
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        return invalid("only alphanumerics, '_' and '-' are allowed");
+    }
+    if formatter::is_builtin_field(name) {
+        return invalid("it is a built-in field");
+    }
+    FIELDS.write().insert(name, UserField(Arc::new(write)));
+    Ok(())
+}
+
+/// The names of the registered fields.
+pub fn names() -> Vec<&'static str> {
+    FIELDS.read().keys().copied().collect()
+}
+
+/// The writer of the registered field `name`.
+pub fn get(name: &str) -> Option<UserField> {
+    FIELDS.read().get(name).cloned()
+}
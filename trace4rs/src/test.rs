@@ -3,14 +3,26 @@
 use std::{
     fs,
     io::Read,
+    path::Path,
+    thread,
+    time::Duration,
 };
 
 use trace4rs_config::config::{
     Appender,
     Config,
+    Dedupe,
     Format,
     LevelFilter,
     Logger,
+    RateLimit,
+    RateLimitKey,
+    Redaction,
+    Replacement,
+    Sample,
+    Sanitize,
+    SpanLog,
+    Timezone,
 };
 use tracing_subscriber::Registry;
 
@@ -23,27 +35,18 @@ fn test_set_global() {
 
     let (handle, subscriber) = {
         let console = Appender::Console;
-        let file = Appender::File {
-            path: file_out.to_string_lossy().into_owned(),
-        };
+        let file = Appender::file(file_out.to_string_lossy());
         let appenders = literally::hmap! {
             "console" => console,
             "file" => file,
         };
-        let default = Logger {
-            level:     LevelFilter::WARN,
-            appenders: literally::hset! {"console"},
-            format:    Format::default(),
-        };
-        let l1 = Logger {
-            level:     LevelFilter::INFO,
-            appenders: literally::hset! {"file"},
-            format:    Format::default(),
-        };
+        let default = Logger::new(LevelFilter::WARN, ["console"]);
+        let l1 = Logger::new(LevelFilter::INFO, ["file"]);
         let config = Config {
             default,
             loggers: literally::hmap! {"trace4rs" => l1},
             appenders,
+            ..Config::default()
         };
 
         Handle::<Registry>::from_config(&config).unwrap()
@@ -74,6 +77,642 @@ fn test_set_global() {
     assert!(file_content.is_empty());
 }
 
+#[test]
+fn test_watch_appender_paths() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let file_out = tmp_guard.path().join("file.log");
+    let file_moved = tmp_guard.path().join("file.log.moved");
+
+    let config = file_config(Format::default(), &file_out);
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+    let _watcher = handle.watch_appender_paths(Duration::from_millis(10));
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("before move");
+        handle.flush().unwrap();
+        fs::rename(&file_out, &file_moved).unwrap();
+
+        // wait for the watcher to notice and remount
+        for _ in 0..100 {
+            if file_out.exists() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        tracing::info!("after move");
+        handle.flush().unwrap();
+    });
+
+    let moved_content = fs::read_to_string(&file_moved).unwrap();
+    assert!(moved_content.contains("before move"));
+    assert!(!moved_content.contains("after move"));
+
+    let file_content = fs::read_to_string(&file_out).unwrap();
+    assert!(file_content.contains("after move"));
+}
+
+#[test]
+fn test_rate_limit() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let file_out = tmp_guard.path().join("file.log");
+
+    let mut config = file_config(Format::MessageOnly, &file_out);
+    config.default.rate_limit = Some(RateLimit {
+        rate:  10,
+        burst: 2,
+        per:   RateLimitKey::Callsite,
+    });
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    let flood = |i| tracing::info!("flood {i}");
+    tracing::subscriber::with_default(subscriber, || {
+        for i in 0..10 {
+            flood(i);
+        }
+        tracing::info!("other callsite");
+        // refill at least one token
+        thread::sleep(Duration::from_millis(150));
+        tracing::info!("other callsite again");
+        for i in 10..20 {
+            flood(i);
+        }
+        // the buckets are swept for summaries once a second, even with
+        // nothing logged
+        thread::sleep(Duration::from_millis(1300));
+        handle.flush().unwrap();
+    });
+
+    let file_content = fs::read_to_string(&file_out).unwrap();
+    let lines: Vec<&str> = file_content.lines().collect();
+    assert_eq!(lines.get(..4), Some(
+        &[
+            "flood 0",
+            "flood 1",
+            "other callsite",
+            "other callsite again",
+        ][..]
+    ));
+    assert!(lines
+        .get(4)
+        .unwrap()
+        .starts_with("suppressed 8 events from callsite trace4rs::test:"));
+    assert_eq!(lines.get(5), Some(&"flood 10"));
+    // the bucket is nowhere near refilled
+    assert!(lines.len() < 10);
+    // the rest of the flood is summarized although the logger went quiet
+    let [.., summary] = lines.as_slice() else {
+        panic!("{file_content}");
+    };
+    assert!(summary.starts_with("suppressed "), "{file_content}");
+    assert!(summary.contains(" events from callsite trace4rs::test:"));
+}
+
+#[test]
+fn test_sample() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let file_out = tmp_guard.path().join("file.log");
+
+    let mut config = file_config(Format::MessageOnly, &file_out);
+    config.default.level = LevelFilter::DEBUG;
+    config.default.sample = Some(Sample {
+        rate:  None,
+        every: Some(10),
+        level: Sample::default_level(),
+    });
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        for i in 0..100 {
+            tracing::debug!("debug {i}");
+            tracing::warn!("warn {i}");
+        }
+        handle.flush().unwrap();
+        assert_eq!(
+            handle.sampled_out_counts().unwrap().get(&None),
+            Some(&90)
+        );
+    });
+
+    let file_content = fs::read_to_string(&file_out).unwrap();
+    let debug = file_content.lines().filter(|l| l.starts_with("debug")).count();
+    let warn = file_content.lines().filter(|l| l.starts_with("warn")).count();
+    assert_eq!(debug, 10);
+    assert_eq!(warn, 100);
+}
+
+#[test]
+fn test_dedupe() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let file_out = tmp_guard.path().join("file.log");
+
+    let mut config = file_config(Format::Normal, &file_out);
+    if let Some(Appender::File { dedupe, .. }) = config.appenders.get_mut(&"file".into()) {
+        *dedupe = Some(Dedupe { window_ms: 60_000 });
+    }
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        for _ in 0..3 {
+            tracing::info!(attempt = 1, "connection lost");
+            thread::sleep(Duration::from_millis(2));
+        }
+        // distinct messages, however alike
+        tracing::info!("3 retries left");
+        tracing::info!("2 retries left");
+        handle.flush().unwrap();
+    });
+
+    let file_content = fs::read_to_string(&file_out).unwrap();
+    let lines: Vec<_> = file_content.lines().collect();
+    let [lost, "last message repeated 2 times", three, two] = lines.as_slice() else {
+        panic!("unexpected output: {file_content}");
+    };
+    assert!(lost.ends_with("connection lost attempt=1"));
+    assert!(three.ends_with("3 retries left"));
+    assert!(two.ends_with("2 retries left"));
+}
+
+#[test]
+fn test_sifting() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let template = tmp_guard.path().join("$FIELD{tenant_id}.log");
+
+    let config = Config {
+        default: logger("tenants", Format::MessageOnly),
+        appenders: literally::hmap! {
+            "tenants" => Appender::sifting("tenant_id", template.to_string_lossy()),
+        },
+        ..Config::default()
+    };
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(tenant_id = "acme", "from the event");
+        tracing::info_span!("request", tenant_id = 42).in_scope(|| {
+            tracing::info!("from the span");
+            tracing::info!(tenant_id = "acme", "the event wins");
+        });
+        tracing::info!(tenant_id = "../escape", "sanitized");
+        for tenant_id in ["a/b", "a:b", "a_b"] {
+            tracing::info!(tenant_id, "{tenant_id}");
+        }
+        tracing::info!("no tenant");
+        handle.flush().unwrap();
+    });
+
+    let read = |name: &str| fs::read_to_string(tmp_guard.path().join(name)).unwrap();
+    assert_eq!(read("acme.log"), "from the event\nthe event wins\n");
+    assert_eq!(read("42.log"), "from the span\n");
+    assert_eq!(read("%2E.%2Fescape.log"), "sanitized\n");
+    // values differing in an unsafe character don't share a file
+    assert_eq!(read("a%2Fb.log"), "a/b\n");
+    assert_eq!(read("a%3Ab.log"), "a:b\n");
+    assert_eq!(read("a_b.log"), "a_b\n");
+    assert_eq!(read("unknown.log"), "no tenant\n");
+}
+
+#[test]
+fn test_span_log() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let main_log = tmp_guard.path().join("main.log");
+    let job_log = |id: &str| tmp_guard.path().join("jobs").join(format!("{id}.log"));
+
+    let mut config = file_config(Format::MessageOnly, &main_log);
+    config.spans = literally::hmap! {
+        "job" => SpanLog {
+            format: Format::MessageOnly,
+            ..SpanLog::new(tmp_guard.path().join("jobs/$FIELD{id}.log").to_string_lossy())
+        },
+    };
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("before");
+        tracing::info_span!("job", id = 42).in_scope(|| {
+            tracing::info!("started");
+            tracing::info_span!("step").in_scope(|| tracing::debug!("nested"));
+            tracing::info_span!("job", id = 7).in_scope(|| tracing::info!("inner job"));
+        });
+        // closing the span closed the file
+        assert_eq!(
+            fs::read_to_string(job_log("42")).unwrap(),
+            "started\nnested\ninner job\n"
+        );
+        tracing::info!("after");
+        handle.flush().unwrap();
+        handle.disable().unwrap();
+        tracing::info_span!("job", id = 9).in_scope(|| tracing::info!("disabled"));
+    });
+
+    assert!(!job_log("9").exists());
+    assert_eq!(fs::read_to_string(job_log("7")).unwrap(), "inner job\n");
+    assert_eq!(
+        fs::read_to_string(&main_log).unwrap(),
+        "before\nstarted\ninner job\nafter\n"
+    );
+}
+
+#[test]
+fn test_redacted_paths() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let dir = tmp_guard.path();
+    let config = Config {
+        default: logger("by_ssn", Format::MessageOnly),
+        appenders: literally::hmap! {
+            "by_ssn" => Appender::sifting("ssn", dir.join("$FIELD{ssn}.log").to_string_lossy()),
+        },
+        spans: literally::hmap! {
+            "job" => SpanLog {
+                format: Format::MessageOnly,
+                ..SpanLog::new(dir.join("jobs/$FIELD{user}.log").to_string_lossy())
+            },
+        },
+        redact: vec![
+            Redaction {
+                field:   Some("ssn".to_string()),
+                pattern: None,
+                replace: Replacement::Mask,
+                key:     None,
+            },
+            Redaction {
+                field:   Some("user".to_string()),
+                pattern: None,
+                replace: Replacement::Remove,
+                key:     None,
+            },
+        ],
+        ..Config::default()
+    };
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(ssn = "123-45-6789", "from the event");
+        tracing::info_span!("request", ssn = "987-65-4321").in_scope(|| {
+            tracing::info!("from the span");
+        });
+        tracing::info_span!("job", user = "alice").in_scope(|| tracing::info!("job"));
+        handle.flush().unwrap();
+    });
+
+    // the values picking the files are redacted as they would be in the
+    // output, the mask then made safe for a file name
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("%2A%2A%2A.log"), "from the event\nfrom the span\n");
+    assert_eq!(read("jobs/unknown.log"), "job\n");
+    assert!(!dir.join("123-45-6789.log").exists());
+    assert!(!dir.join("jobs/alice.log").exists());
+}
+
+#[test]
+fn test_format_once() {
+    use std::{
+        fmt,
+        sync::atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+    };
+
+    static FORMATTED: AtomicUsize = AtomicUsize::new(0);
+    struct Counted;
+    impl fmt::Debug for Counted {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            FORMATTED.fetch_add(1, Ordering::Relaxed);
+            f.write_str("counted")
+        }
+    }
+
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let path = |name: &str| tmp_guard.path().join(name).to_string_lossy().into_owned();
+    let custom = Format::Custom("{l} {f}".to_string());
+    let config = Config {
+        default: logger("a", Format::Normal),
+        loggers: literally::hmap! {
+            "trace4rs" => logger("a", custom.clone()),
+            "trace4rs::test" => logger("b", custom.clone()),
+            "trace4rs::test::format_once" => logger("c", Format::Normal),
+        },
+        appenders: literally::hmap! {
+            "a" => Appender::file(path("a.log")),
+            "b" => Appender::file(path("b.log")),
+            "c" => Appender::file(path("c.log")),
+        },
+        ..Config::default()
+    };
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(target: "trace4rs::test::format_once", counted = ?Counted);
+        handle.flush().unwrap();
+    });
+
+    // once for the custom format shared by two loggers, once for the other
+    assert_eq!(FORMATTED.load(Ordering::Relaxed), 2);
+    let a = fs::read_to_string(path("a.log")).unwrap();
+    assert_eq!(a, "INFO counted=counted\n");
+    assert_eq!(fs::read_to_string(path("b.log")).unwrap(), a);
+    assert!(
+        fs::read_to_string(path("c.log"))
+            .unwrap()
+            .ends_with("trace4rs::test::format_once: counted=counted\n")
+    );
+}
+
+#[test]
+fn test_timestamp_format() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let path = |name: &str| tmp_guard.path().join(name).to_string_lossy().into_owned();
+    let stamped = |appender: &str, format: Format, timestamp: &str| {
+        let mut stamped = logger(appender, format);
+        stamped.timezone = Timezone::Utc;
+        stamped.timestamp = Some(timestamp.to_string());
+        stamped
+    };
+    let pattern = "{T} {T:[year]-[month]-[day]} {T(utc):epoch_ms} {m}";
+    let config = Config {
+        default: stamped("normal", Format::Normal, "epoch"),
+        loggers: literally::hmap! {
+            "trace4rs" => stamped("custom", Format::Custom(pattern.to_string()), "[hour]:[minute]"),
+        },
+        appenders: literally::hmap! {
+            "normal" => Appender::file(path("normal.log")),
+            "custom" => Appender::file(path("custom.log")),
+        },
+        ..Config::default()
+    };
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(target: "other", "hello");
+        tracing::info!("hello");
+        handle.flush().unwrap();
+    });
+
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let normal = fs::read_to_string(path("normal.log")).unwrap();
+    let (secs, _) = normal.split_once(' ').unwrap();
+    assert!(is_digits(secs) && secs.len() >= 10, "{normal}");
+
+    let custom = fs::read_to_string(path("custom.log")).unwrap();
+    let words: Vec<&str> = custom.split(' ').collect();
+    let [time, date, millis, "hello\n"] = words.as_slice() else {
+        panic!("{custom}");
+    };
+    assert!(
+        time.len() == 5 && time.split(':').all(|p| p.len() == 2 && is_digits(p)),
+        "{time}"
+    );
+    assert!(date.len() == 10 && date.split('-').all(is_digits), "{date}");
+    assert!(is_digits(millis) && millis.len() >= 13, "{millis}");
+}
+
+#[test]
+fn test_span_fields() {
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let path = tmp_guard.path().join("spans.log");
+    let config = file_config(
+        Format::Custom("[{s}] [{S}] [{S.fields}] {m}".to_string()),
+        &path,
+    );
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("outside");
+        let request = tracing::info_span!("request", request_id = 7, user = tracing::field::Empty);
+        request.in_scope(|| {
+            tracing::info_span!("auth").in_scope(|| tracing::info!("checking"));
+            request.record("user", "bob");
+            tracing::info_span!("db", table = "users").in_scope(|| tracing::info!("query"));
+        });
+        handle.flush().unwrap();
+    });
+
+    let content = fs::read_to_string(path).unwrap();
+    assert_eq!(
+        content,
+        "[] [] [] outside\n[auth] [request:auth] [request_id=7] checking\n[db] [request:db] \
+         [request_id=7 user=\"bob\" table=\"users\"] query\n"
+    );
+}
+
+#[test]
+fn test_context() {
+    std::env::set_var("TRACE4RS_TEST_VERSION", "1.2.3");
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let normal = tmp_guard.path().join("normal.log");
+    let custom = tmp_guard.path().join("custom.log");
+    let pattern = "{ctx.service}@{ctx.version} {ctx.region:default=-}: {m}";
+    let config = Config {
+        default: logger("normal", Format::Normal),
+        loggers: literally::hmap! {
+            "trace4rs::test" => {
+                let mut both = Logger::new(LevelFilter::INFO, ["normal", "custom"]);
+                both.format = Format::Custom(pattern.to_string());
+                both
+            },
+        },
+        appenders: literally::hmap! {
+            "normal" => Appender::file(normal.to_string_lossy()),
+            "custom" => Appender::file(custom.to_string_lossy()),
+        },
+        context: literally::hmap! {
+            "service" => "api",
+            "version" => "$ENV{TRACE4RS_TEST_VERSION}",
+        },
+        ..Config::default()
+    };
+    let (mut handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("before");
+        handle.set_context("region", "eu").unwrap();
+        tracing::info!("after");
+        handle.update(&config).unwrap();
+        tracing::info!("updated");
+        tracing::info!(target: "other", user = "bob", "normal");
+        handle.flush().unwrap();
+    });
+
+    let content = fs::read_to_string(custom).unwrap();
+    assert_eq!(
+        content,
+        "api@1.2.3 -: before\napi@1.2.3 eu: after\napi@1.2.3 eu: updated\n"
+    );
+    let normal_content = fs::read_to_string(normal).unwrap();
+    let line = normal_content.lines().last().unwrap();
+    assert!(
+        line.ends_with(r#"other: normal user="bob" service="api" version="1.2.3" region="eu""#),
+        "{line}"
+    );
+}
+
+#[test]
+fn test_redaction() {
+    macro_rules! pay {
+        ($target:literal) => {
+            tracing::info!(
+                target: $target,
+                password = "hunter2",
+                token = "s3cr3t",
+                card = 4_111_111_111_111_111_u64,
+                user = "bob",
+                "bob paid with 4111-1111-1111-1111"
+            );
+        };
+    }
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let normal = tmp_guard.path().join("normal.log");
+    let message = tmp_guard.path().join("message.log");
+    let custom = tmp_guard.path().join("custom.log");
+    let config = Config {
+        default: logger("normal", Format::Normal),
+        loggers: literally::hmap! {
+            "normal" => logger("normal", Format::Normal),
+            "message" => logger("message", Format::MessageOnly),
+            "custom" => logger(
+                "custom",
+                Format::Custom("{S.fields}: {f} / {f.token} {f.password:default=-}".to_string()),
+            ),
+        },
+        appenders: literally::hmap! {
+            "normal" => Appender::file(normal.to_string_lossy()),
+            "message" => Appender::file(message.to_string_lossy()),
+            "custom" => Appender::file(custom.to_string_lossy()),
+        },
+        redact: vec![
+            Redaction {
+                field:   Some("*PASSWORD*".to_string()),
+                pattern: None,
+                replace: Replacement::Remove,
+                key:     None,
+            },
+            Redaction {
+                field:   Some("token".to_string()),
+                pattern: None,
+                replace: Replacement::Hash,
+                key:     Some("pepper".to_string()),
+            },
+            Redaction {
+                field:   None,
+                pattern: Some(r"\b\d{4}(?:-?\d{4}){3}\b".to_string()),
+                replace: Replacement::Mask,
+                key:     None,
+            },
+        ],
+        ..Config::default()
+    };
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", password = "pw", id = 4_111_111_111_111_111_u64);
+        let _guard = span.enter();
+        pay!("normal");
+        pay!("message");
+        pay!("custom");
+        handle.flush().unwrap();
+    });
+
+    let hash = "e6cc30076e0652cb";
+    let normal_content = fs::read_to_string(normal).unwrap();
+    assert!(
+        normal_content.ends_with(&format!(
+            "normal: bob paid with *** token=\"{hash}\" card=*** user=\"bob\"\n"
+        )),
+        "{normal_content}"
+    );
+    assert_eq!(fs::read_to_string(message).unwrap(), "bob paid with ***\n");
+    assert_eq!(
+        fs::read_to_string(custom).unwrap(),
+        format!("id=***: bob paid with *** token=\"{hash}\" card=*** user=\"bob\" / {hash} -\n")
+    );
+}
+
+#[test]
+fn test_sanitize() {
+    macro_rules! forge {
+        ($target:literal) => {
+            tracing::info!(
+                target: $target,
+                user = "bob\nroot",
+                "denied\nINFO granted \x1b[31mred\x1b[0m"
+            );
+        };
+    }
+    let sanitized = |appender: &str, format: Format, sanitize: Sanitize| {
+        let mut sanitized = logger(appender, format);
+        sanitized.sanitize = Some(sanitize);
+        sanitized
+    };
+    let tmp_guard = tempfile::tempdir().unwrap();
+    let normal = tmp_guard.path().join("normal.log");
+    let message = tmp_guard.path().join("message.log");
+    let custom = tmp_guard.path().join("custom.log");
+    let indent = tmp_guard.path().join("indent.log");
+    let config = Config {
+        default: sanitized("normal", Format::Normal, Sanitize::Escape),
+        loggers: literally::hmap! {
+            "normal" => sanitized("normal", Format::Normal, Sanitize::Escape),
+            "message" => sanitized("message", Format::MessageOnly, Sanitize::Escape),
+            "custom" => sanitized(
+                "custom",
+                Format::Custom("{m} user={f.user}{n}".to_string()),
+                Sanitize::Escape,
+            ),
+            "indent" => sanitized("indent", Format::MessageOnly, Sanitize::Indent),
+        },
+        appenders: literally::hmap! {
+            "normal" => Appender::file(normal.to_string_lossy()),
+            "message" => Appender::file(message.to_string_lossy()),
+            "custom" => Appender::file(custom.to_string_lossy()),
+            "indent" => Appender::file(indent.to_string_lossy()),
+        },
+        ..Config::default()
+    };
+    let (handle, subscriber) = Handle::<Registry>::from_config(&config).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        forge!("normal");
+        forge!("message");
+        forge!("custom");
+        tracing::info!(target: "indent", "panicked at 'boom'\r\nstack backtrace:\n   0: main");
+        handle.flush().unwrap();
+    });
+
+    // the normal format escapes ANSI sequences itself, but not line breaks
+    let normal_content = fs::read_to_string(normal).unwrap();
+    assert_eq!(normal_content.lines().count(), 1, "{normal_content}");
+    assert!(!normal_content.contains('\x1b'), "{normal_content}");
+    assert!(normal_content.contains(r"normal: denied\nINFO granted "), "{normal_content}");
+    assert!(normal_content.ends_with(" user=\"bob\\nroot\"\n"), "{normal_content}");
+    let escaped = r"denied\nINFO granted \u{1b}[31mred\u{1b}[0m";
+    assert_eq!(fs::read_to_string(message).unwrap(), format!("{escaped}\n"));
+    assert_eq!(
+        fs::read_to_string(custom).unwrap(),
+        format!("{escaped} user=bob\\nroot\n\n")
+    );
+    assert_eq!(
+        fs::read_to_string(indent).unwrap(),
+        "panicked at 'boom'\n    stack backtrace:\n       0: main\n"
+    );
+}
+
+/// A logger of `INFO` and above in `format` to `appender`.
+fn logger(appender: &str, format: Format) -> Logger {
+    let mut logger = Logger::new(LevelFilter::INFO, [appender]);
+    logger.format = format;
+    logger
+}
+
+/// A config with the default logger writing in `format` to the file at
+/// `path`.
+fn file_config(format: Format, path: &Path) -> Config {
+    Config {
+        default: logger("file", format),
+        appenders: literally::hmap! {"file" => Appender::file(path.to_string_lossy())},
+        ..Config::default()
+    }
+}
+
 fn each_level() {
     tracing::trace!("hello trace");
     tracing::debug!("hello debug");
@@ -39,6 +39,11 @@ fn test_custom_fmt() {
                 "appenders": ["file1"],
                 "format": "{T} {t}: {l} {f} {foo}",
                 "level": "TRACE"
+            },
+            "three": {
+                "appenders": ["file1"],
+                "format": "{module} {file}:{line} {pid}: {m}",
+                "level": "TRACE"
             }
         }
     }))
@@ -65,4 +70,13 @@ fn test_custom_fmt() {
     assert!(f1_content.contains("logging to one --"));
     assert!(f1_content.contains("INFO two: logging to two"));
     assert!(f1_content.contains("baz=\"foobarish\""));
+
+    // the location of a `log` record comes from the record
+    log::info!(target: "three", "logging to three");
+    let line = line!() - 1;
+    sleep(Duration::from_millis(100));
+    let located = fs::read_to_string("./file1.log").unwrap();
+    println!("{located}");
+    let pid = std::process::id();
+    assert!(located.contains(&format!("fmt {}:{line} {pid}: logging to three", file!())));
 }
@@ -29,7 +29,12 @@ fn test_de_ser() {
             "rolloverPolicy": {
                 "maximumFileSize": "1mb",
                 "maxSizeRollBackups": 3
-            }
+            },
+            "onError": "reportOnce",
+            "sync": {
+                "everyEvents": 100
+            },
+            "audit": true
         },
         "file3": {
             "kind": "rollingfile",
@@ -38,14 +43,67 @@ fn test_de_ser() {
                 "maximumFileSize": "1mb",
                 "maxSizeRollBackups": 3,
                 "pattern": "foobar.log.roll.{}"
+            },
+            "onError": {
+                "retry": {
+                    "attempts": 3,
+                    "backoffMs": 10
+                }
             }
+        },
+        "file4": {
+            "kind": "file",
+            "path": "./foobar.log",
+            "onError": {
+                "failover": "file1"
+            },
+            "dedupe": {
+                "windowMs": 30000
+            },
+            "integrity": {
+                "key": "$ENV{LOG_KEY}"
+            }
+        },
+        "tenants": {
+            "kind": "sifting",
+            "field": "tenant_id",
+            "path": "./logs/$FIELD{tenant_id}.log",
+            "rolloverPolicy": {
+                "maximumFileSize": "1mb",
+                "maxSizeRollBackups": 3
+            },
+            "fallback": "unknown",
+            "idleTimeoutMs": 60000,
+            "maxOpen": 16
         }
     },
     "loggers": {
         "my_target": {
             "appenders": ["file1"],
-            "level": "WARN"
+            "level": "WARN",
+            "rateLimit": {
+                "rate": 100,
+                "burst": 10,
+                "per": "target"
+            },
+            "sample": {
+                "rate": 0.01,
+                "level": "DEBUG"
+            },
+            "timezone": "+02:00",
+            "timestamp": "[hour]:[minute]:[second].[subsecond digits:3]",
+            "sanitize": "indent"
+        }
+    },
+    "spans": {
+        "job": {
+            "path": "./jobs/$FIELD{id}.log",
+            "level": "INFO",
+            "format": "messageonly"
         }
+    },
+    "context": {
+        "service": "$ENV{SERVICE_NAME}"
     }
 }"#
     .chars()
@@ -58,6 +116,44 @@ fn test_de_ser() {
     assert_eq!(conf, ser_ed)
 }
 
+#[test]
+fn test_redact_de_ser() {
+    let conf: String = r#"{
+    "root": {
+        "appenders": ["stdout"],
+        "level": "INFO"
+    },
+    "appenders": {
+        "stdout": {
+            "kind": "console"
+        }
+    },
+    "loggers": {},
+    "redact": [
+        {
+            "field": "*password*",
+            "replace": "remove"
+        },
+        {
+            "field": "token",
+            "replace": "hash",
+            "key": "$ENV{REDACT_KEY}"
+        },
+        {
+            "pattern": "\\b\\d{16}\\b"
+        }
+    ]
+}"#
+    .chars()
+    .filter(|c| !c.is_whitespace())
+    .collect();
+
+    let parsed: Config = serde_json::from_str(&conf).unwrap();
+    let ser_ed = serde_json::to_string(&parsed).unwrap();
+
+    assert_eq!(conf, ser_ed);
+}
+
 #[test]
 fn test_de() {
     // Lets not leave the git dir filthy.
@@ -114,9 +210,10 @@ fn test_de() {
     assert_eq!(my_target.appenders.iter().next().unwrap(), file1);
 
     let file1_appender = parsed.appenders.get(file1).unwrap();
-    assert_eq!(file1_appender, &Appender::File {
-        path: format!("{tmp_path}/foobar.log"),
-    });
+    assert_eq!(
+        file1_appender,
+        &Appender::file(format!("{tmp_path}/foobar.log"))
+    );
 
     // now lets convert this to a Handle
     let (_handle, _s): (Handle, _) = Handle::from_config(&parsed).unwrap();